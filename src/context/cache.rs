@@ -0,0 +1,257 @@
+//! Cache bindings to locate files shipped via the distributed cache.
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::Configuration;
+
+/// DistributedCache structure to locate files shipped with a job.
+///
+/// Files passed via `-files` and `-archives` are listed in the job
+/// `Configuration`, and linked into the task working directory by Hadoop
+/// using either the URI fragment (e.g. `hdfs:///lookup.txt#lookup`) or
+/// the file name. Each entry is resolved to this link, falling back to
+/// the path itself for local files so that the same job can run outside
+/// of Hadoop.
+#[derive(Clone, Debug, Default)]
+pub struct DistributedCache {
+    files: Vec<CacheFile>,
+    archives: Vec<CacheFile>,
+}
+
+/// CacheFile structure to represent a single distributed cache entry.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CacheFile {
+    uri: String,
+    name: String,
+    path: PathBuf,
+}
+
+impl DistributedCache {
+    /// Creates a new `DistributedCache` from a job `Configuration`.
+    pub fn new(conf: &Configuration) -> Self {
+        let dir = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        Self::with_dir(conf, dir)
+    }
+
+    /// Creates a new `DistributedCache` linked into a custom directory.
+    pub fn with_dir<P: AsRef<Path>>(conf: &Configuration, dir: P) -> Self {
+        let dir = dir.as_ref();
+        let entries = |key, deprecated| {
+            conf.get_list(key)
+                .or_else(|| conf.get_list(deprecated))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|uri| CacheFile::new(uri, dir))
+                .collect()
+        };
+
+        Self {
+            files: entries("mapreduce.job.cache.files", "mapred.cache.files"),
+            archives: entries("mapreduce.job.cache.archives", "mapred.cache.archives"),
+        }
+    }
+
+    /// Retrieves all files shipped with the job.
+    pub fn files(&self) -> &[CacheFile] {
+        &self.files
+    }
+
+    /// Retrieves all archives shipped with the job.
+    ///
+    /// Archives are extracted by Hadoop, so the path of each archive
+    /// is a directory containing the archive contents.
+    pub fn archives(&self) -> &[CacheFile] {
+        &self.archives
+    }
+
+    /// Retrieves a cache entry by name, checking files before archives.
+    pub fn get(&self, name: &str) -> Option<&CacheFile> {
+        self.files
+            .iter()
+            .chain(self.archives.iter())
+            .find(|file| file.name == name)
+    }
+
+    /// Retrieves the local path of a cache entry by name.
+    pub fn path(&self, name: &str) -> Option<&Path> {
+        self.get(name).map(CacheFile::path)
+    }
+
+    /// Opens a cache file by name.
+    pub fn open(&self, name: &str) -> io::Result<File> {
+        self.lookup(name)?.open()
+    }
+
+    /// Reads the contents of a cache file by name.
+    pub fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        self.lookup(name)?.read()
+    }
+
+    /// Reads the contents of a cache file by name into a `String`.
+    pub fn read_to_string(&self, name: &str) -> io::Result<String> {
+        self.lookup(name)?.read_to_string()
+    }
+
+    /// Memory maps a cache file by name.
+    ///
+    /// See `CacheFile::map` for the requirements of mapping a file.
+    #[cfg(feature = "mmap")]
+    pub fn map(&self, name: &str) -> io::Result<memmap2::Mmap> {
+        self.lookup(name)?.map()
+    }
+
+    /// Retrieves a cache entry by name, or a `NotFound` error.
+    fn lookup(&self, name: &str) -> io::Result<&CacheFile> {
+        self.get(name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no distributed cache entry named {}", name),
+            )
+        })
+    }
+}
+
+impl CacheFile {
+    /// Creates a new `CacheFile` from a URI, resolved against a directory.
+    fn new(uri: &str, dir: &Path) -> Self {
+        let (location, fragment) = match uri.split_once('#') {
+            Some((location, fragment)) => (location, Some(fragment)),
+            None => (uri, None),
+        };
+
+        // links are named by the fragment, or the file name
+        let name = fragment
+            .filter(|fragment| !fragment.is_empty())
+            .unwrap_or_else(|| location.trim_end_matches('/').rsplit('/').next().unwrap());
+
+        let link = dir.join(name);
+
+        // local files can be used directly when there's no link
+        let local = match location.strip_prefix("file://") {
+            Some(path) => Some(Path::new(path)),
+            None if !location.contains("://") => Some(Path::new(location)),
+            None => None,
+        };
+
+        let path = match local {
+            Some(local) if !link.exists() && local.exists() => local.to_path_buf(),
+            _ => link,
+        };
+
+        Self {
+            uri: uri.to_owned(),
+            name: name.to_owned(),
+            path,
+        }
+    }
+
+    /// Retrieves the URI the entry was shipped from.
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    /// Retrieves the name of the entry within the working directory.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Retrieves the local path of the entry.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Opens the entry as a `File`.
+    pub fn open(&self) -> io::Result<File> {
+        File::open(&self.path)
+    }
+
+    /// Reads the contents of the entry.
+    pub fn read(&self) -> io::Result<Vec<u8>> {
+        fs::read(&self.path)
+    }
+
+    /// Reads the contents of the entry into a `String`.
+    pub fn read_to_string(&self) -> io::Result<String> {
+        fs::read_to_string(&self.path)
+    }
+
+    /// Memory maps the contents of the entry.
+    ///
+    /// Cache files are read-only for the lifetime of a task, which is what
+    /// makes mapping them safe; the file must not be modified (or truncated)
+    /// whilst the map is alive.
+    #[cfg(feature = "mmap")]
+    pub fn map(&self) -> io::Result<memmap2::Mmap> {
+        let file = self.open()?;
+
+        // safe as long as the file is not modified, as documented above
+        unsafe { memmap2::Mmap::map(&file) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_resolution() {
+        let dir = env::temp_dir().join(format!("efflux-cache-{}", std::process::id()));
+        let local = dir.join("local.txt");
+
+        fs::create_dir_all(dir.join("dict.zip")).unwrap();
+        fs::write(dir.join("lookup"), "linked").unwrap();
+        fs::write(&local, "local").unwrap();
+
+        let mut conf = Configuration::default();
+
+        conf.insert(
+            "mapreduce.job.cache.files",
+            &*format!(
+                "hdfs://nn/data/lookup.txt#lookup, file://{}#other",
+                local.display()
+            ),
+        );
+        conf.insert("mapred.cache.archives", "hdfs://nn/data/dict.zip");
+
+        let cache = DistributedCache::with_dir(&conf, &dir);
+
+        let names = cache
+            .files()
+            .iter()
+            .map(CacheFile::name)
+            .collect::<Vec<_>>();
+
+        assert_eq!(names, vec!["lookup", "other"]);
+        assert_eq!(
+            cache.get("lookup").unwrap().uri(),
+            "hdfs://nn/data/lookup.txt#lookup"
+        );
+        assert_eq!(cache.path("lookup"), Some(&*dir.join("lookup")));
+        assert_eq!(cache.path("other"), Some(&*local));
+        assert_eq!(cache.path("dict.zip"), Some(&*dir.join("dict.zip")));
+        assert_eq!(cache.archives().len(), 1);
+
+        assert_eq!(cache.read_to_string("lookup").unwrap(), "linked");
+        assert_eq!(cache.read("other").unwrap(), b"local");
+        assert_eq!(
+            cache.open("missing").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        #[cfg(feature = "mmap")]
+        assert_eq!(&cache.map("lookup").unwrap()[..], b"linked");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cache_defaults() {
+        let cache = DistributedCache::new(&Configuration::default());
+
+        assert!(cache.files().is_empty());
+        assert!(cache.archives().is_empty());
+        assert!(cache.get("lookup").is_none());
+    }
+}
@@ -1,21 +1,71 @@
 //! Module to provide representation of the Hadoop `Configuration` class.
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::Error;
 
 /// Configuration struct to represent a Hadoop configuration.
 ///
 /// Internally this is simply a `String` -> `String` map, as
 /// we don't have enough information to parse with. The struct
 /// implementation exists as a compatibility layer.
-#[derive(Debug, Default)]
+///
+/// Values can be loaded from the environment provided by Hadoop
+/// Streaming, or from Hadoop's XML resources (such as `job.xml`).
+/// Keys are stored as provided, but Streaming exports keys with any
+/// non-alphanumeric characters replaced by `_`; lookups fall back to
+/// this form so that `mapred.job.id` finds `mapred_job_id` (and vice
+/// versa), with the most recently set value taking priority.
+#[derive(Clone, Debug, Default)]
 pub struct Configuration {
-    inner: HashMap<String, String>,
+    inner: HashMap<String, Entry>,
+    aliases: HashMap<String, String>,
+    finals: HashSet<String>,
+    references: HashSet<String>,
+    failures: HashSet<String>,
+    sequence: u64,
+}
+
+/// Source enum to represent where a `Configuration` value came from.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Source {
+    /// Loaded from the environment, as exported by Hadoop Streaming.
+    Env,
+    /// Loaded from an XML resource, with the path if known.
+    Xml(Option<PathBuf>),
+    /// Set programmatically via `Configuration::insert`.
+    Programmatic,
+}
+
+/// Entry structure to represent a value stored in a `Configuration`.
+#[derive(Clone, Debug)]
+struct Entry {
+    raw: String,
+    value: String,
+    source: Source,
+    sequence: u64,
 }
 
 impl Configuration {
     /// Constructs a new `Configuration` using Hadoop's input.
+    ///
+    /// The `job.xml` in the task working directory is loaded first (if
+    /// it exists), with the environment taking priority over it.
     pub fn new() -> Self {
-        Self::with_env(env::vars())
+        let mut conf = Self::default();
+
+        if Path::new("job.xml").is_file() {
+            if let Err(err) = conf.load("job.xml") {
+                log!("efflux: {}", err);
+            }
+        }
+
+        conf.extend_env(env::vars());
+        conf
     }
 
     /// Constructs a new `Configuration` using a custom input.
@@ -24,59 +74,497 @@ impl Configuration {
         T: Into<String>,
         I: Iterator<Item = (T, T)>,
     {
-        // create container
-        let mut conf = Self {
-            inner: HashMap::new(),
+        let mut conf = Self::default();
+        conf.extend_env(pairs);
+        conf
+    }
+
+    /// Loads a Hadoop XML resource (such as `core-site.xml`) from a path.
+    ///
+    /// See `load_xml` for how the resource is merged.
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        let xml = fs::read_to_string(path)
+            .map_err(|err| Error::Config(format!("unable to read {}: {}", path.display(), err)))?;
+
+        self.load_resource(&xml, Source::Xml(Some(path.to_path_buf())))
+            .map_err(|err| Error::Config(format!("unable to load {}: {}", path.display(), err)))
+    }
+
+    /// Loads a Hadoop XML resource into the `Configuration`.
+    ///
+    /// Values from the resource override any existing values, unless an
+    /// earlier resource marked them as `final`. As in Hadoop, `${var}`
+    /// references are substituted using other `Configuration` values, or
+    /// environment variables via `${env.VAR}`. References are resolved
+    /// against all sources, and again whenever a value changes, so they
+    /// may refer to values loaded or inserted later.
+    pub fn load_xml(&mut self, xml: &str) -> Result<(), Error> {
+        self.load_resource(xml, Source::Xml(None))
+    }
+
+    /// Retrieves a potential `Configuration` value.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entry(key).map(|entry| entry.value.as_ref())
+    }
+
+    /// Retrieves the `Source` of a potential `Configuration` value.
+    pub fn source(&self, key: &str) -> Option<&Source> {
+        self.entry(key).map(|entry| &entry.source)
+    }
+
+    /// Retrieves an iterator over all keys and values, as provided.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.inner
+            .iter()
+            .map(|(key, entry)| (&key[..], &entry.value[..]))
+    }
+
+    /// Loads a Hadoop XML resource from the provided `Source`.
+    fn load_resource(&mut self, xml: &str, source: Source) -> Result<(), Error> {
+        let options = roxmltree::ParsingOptions {
+            allow_dtd: true,
+            ..Default::default()
+        };
+
+        let doc = roxmltree::Document::parse_with_options(xml, options)
+            .map_err(|err| Error::Config(err.to_string()))?;
+
+        let root = doc.root_element();
+
+        if !root.has_tag_name("configuration") {
+            return Err(Error::Config(format!(
+                "expected <configuration>, found <{}>",
+                root.tag_name().name()
+            )));
+        }
+
+        for property in root.children().filter(|n| n.has_tag_name("property")) {
+            let field = |name| {
+                property
+                    .children()
+                    .find(|n| n.has_tag_name(name))
+                    .map(|n| n.text().unwrap_or(""))
+            };
+
+            // properties without a name or value are ignored
+            let (name, value) = match (field("name"), field("value")) {
+                (Some(name), Some(value)) if !name.trim().is_empty() => (name.trim(), value),
+                _ => continue,
+            };
+
+            // final values can't be overridden by later resources
+            if self.finals.contains(name) {
+                log!("efflux: ignoring override of final parameter {}", name);
+                continue;
+            }
+
+            if field("final").map(str::trim) == Some("true") {
+                self.finals.insert(name.to_owned());
+            }
+
+            self.store(name.to_owned(), value.to_owned(), source.clone());
+        }
+
+        // substitute after loading, as values may reference later values
+        self.resolve()
+    }
+
+    /// Retrieves a `Configuration` value parsed via `FromStr`.
+    ///
+    /// Surrounding whitespace is trimmed before parsing, and values
+    /// which fail to parse are treated as missing.
+    pub fn get_parsed<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.parse(key, "a valid value", |val| val.parse().ok())
+            .ok()
+            .flatten()
+    }
+
+    /// Retrieves a `Configuration` value as a `bool`.
+    ///
+    /// As in Hadoop, only `"true"` and `"false"` are accepted (ignoring
+    /// case and surrounding whitespace).
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        self.parse(key, "a boolean", parse_bool).ok().flatten()
+    }
+
+    /// Retrieves a `Configuration` value as a comma separated list.
+    ///
+    /// Each entry is trimmed, and empty entries are removed.
+    pub fn get_list(&self, key: &str) -> Option<Vec<&str>> {
+        self.get(key).map(parse_list)
+    }
+
+    /// Retrieves a `Configuration` value as a number of bytes.
+    ///
+    /// Values may use a binary suffix of `k`, `m`, `g`, `t`, `p` or `e`
+    /// (ignoring case), so `128m` is parsed as `134217728`.
+    pub fn get_bytes(&self, key: &str) -> Option<u64> {
+        self.parse(key, "a byte size", parse_bytes).ok().flatten()
+    }
+
+    /// Retrieves a `Configuration` value as a `Duration`.
+    ///
+    /// Values may use a suffix of `ns`, `us`, `ms`, `s`, `m`, `h` or `d`
+    /// and are treated as milliseconds without one, as most Hadoop
+    /// timeouts are.
+    pub fn get_duration(&self, key: &str) -> Option<Duration> {
+        self.parse(key, "a duration", parse_duration).ok().flatten()
+    }
+
+    /// Retrieves a required `Configuration` value.
+    pub fn require(&self, key: &str) -> Result<&str, Error> {
+        self.get(key).ok_or_else(|| missing(key))
+    }
+
+    /// Retrieves a required `Configuration` value parsed via `FromStr`.
+    pub fn require_parsed<T: FromStr>(&self, key: &str) -> Result<T, Error> {
+        self.parse(key, "a valid value", |val| val.parse().ok())?
+            .ok_or_else(|| missing(key))
+    }
+
+    /// Retrieves a required `Configuration` value as a `bool`.
+    pub fn require_bool(&self, key: &str) -> Result<bool, Error> {
+        self.parse(key, "a boolean", parse_bool)?
+            .ok_or_else(|| missing(key))
+    }
+
+    /// Retrieves a required `Configuration` value as a comma separated list.
+    pub fn require_list(&self, key: &str) -> Result<Vec<&str>, Error> {
+        self.require(key).map(parse_list)
+    }
+
+    /// Retrieves a required `Configuration` value as a number of bytes.
+    pub fn require_bytes(&self, key: &str) -> Result<u64, Error> {
+        self.parse(key, "a byte size", parse_bytes)?
+            .ok_or_else(|| missing(key))
+    }
+
+    /// Retrieves a required `Configuration` value as a `Duration`.
+    pub fn require_duration(&self, key: &str) -> Result<Duration, Error> {
+        self.parse(key, "a duration", parse_duration)?
+            .ok_or_else(|| missing(key))
+    }
+
+    /// Inserts a key/value pair into the `Configuration`.
+    pub fn insert<T>(&mut self, key: T, val: T)
+    where
+        T: Into<String>,
+    {
+        self.store(key.into(), val.into(), Source::Programmatic);
+        self.resolve_logged();
+    }
+
+    /// Stores a raw value in the internal mapping, tracking the `Source`.
+    ///
+    /// Values are only substituted once `resolve` is called, and only
+    /// values containing references are tracked for substitution.
+    fn store(&mut self, key: String, value: String, source: Source) {
+        self.sequence += 1;
+
+        if find_reference(&value).is_some() {
+            self.references.insert(key.clone());
+        } else {
+            self.references.remove(&key);
+        }
+
+        // a new value may fail again, so it's reported again
+        self.failures.remove(&key);
+
+        let entry = Entry {
+            raw: value.clone(),
+            value,
+            source,
+            sequence: self.sequence,
         };
 
-        // iterate all pairs
+        self.aliases.insert(mangle(&key), key.clone());
+        self.inner.insert(key, entry);
+    }
+
+    /// Retrieves the most recently set `Entry` for a key.
+    fn entry(&self, key: &str) -> Option<&Entry> {
+        let exact = self.inner.get(key);
+        let mangled = mangle(key);
+
+        let alias = if mangled == key {
+            // env style lookups match any key with the same mangled form
+            self.aliases
+                .get(&mangled)
+                .and_then(|original| self.inner.get(original))
+        } else {
+            // only the environment mangles keys, so other sources can't match
+            self.inner
+                .get(&mangled)
+                .filter(|entry| entry.source == Source::Env)
+        };
+
+        match (exact, alias) {
+            (Some(exact), Some(alias)) if alias.sequence > exact.sequence => Some(alias),
+            (exact, alias) => exact.or(alias),
+        }
+    }
+
+    /// Inserts pairs from the environment into the `Configuration`.
+    fn extend_env<I, T>(&mut self, pairs: I)
+    where
+        T: Into<String>,
+        I: Iterator<Item = (T, T)>,
+    {
         for (key, val) in pairs {
             let key = key.into();
             let val = val.into();
 
-            // hadoop never has uppercased values
-            if key.chars().any(|c| c.is_uppercase()) {
+            // hadoop properties always begin with a lowercase letter
+            if !key.starts_with(|c: char| c.is_ascii_lowercase()) {
                 continue;
             }
 
             // insert the key/value pair
-            conf.insert(key, val);
+            self.store(key, val, Source::Env);
         }
 
-        conf
+        self.resolve_logged();
     }
 
-    /// Retrieves a potential `Configuration` value.
-    pub fn get(&self, key: &str) -> Option<&str> {
-        // shimming for hadoop
-        let opt = if key.contains('.') {
-            self.inner.get(&key.replace(".", "_"))
-        } else {
-            self.inner.get(key)
-        };
+    /// Substitutes references in values, using the process environment.
+    fn resolve(&mut self) -> Result<(), Error> {
+        self.resolve_with(|name| env::var(name).ok())
+    }
 
-        // better than &String
-        opt.map(|s| s.as_ref())
+    /// Substitutes references in values, logging any new failure.
+    ///
+    /// This is used when a value is set directly, as there's no way to
+    /// return the error; the raw value of the failed entry is kept.
+    fn resolve_logged(&mut self) {
+        if let Err(err) = self.resolve() {
+            log!("efflux: {}", err);
+        }
     }
 
-    /// Inserts a key/value pair into the `Configuration`.
-    pub fn insert<T>(&mut self, key: T, val: T)
+    /// Substitutes references in values, using a custom environment.
+    ///
+    /// Only values containing references are resolved, from the raw values
+    /// of the values they reference, so the result doesn't depend on the
+    /// order in which values were set. Values which fail to resolve are
+    /// left raw, and the first failure not already reported is returned.
+    fn resolve_with<F>(&mut self, env: F) -> Result<(), Error>
     where
-        T: Into<String>,
+        F: Fn(&str) -> Option<String>,
     {
-        // convert to String
-        let mut key_str = key.into();
+        let mut resolved = Vec::with_capacity(self.references.len());
+        let mut failure = None;
+
+        for key in &self.references {
+            let raw = &self.inner[key].raw;
+
+            match self.substitute(raw, &env) {
+                Ok(value) => resolved.push((key.clone(), value, false)),
+                Err(err) => {
+                    if !self.failures.contains(key) {
+                        failure.get_or_insert(err);
+                    }
+                    resolved.push((key.clone(), raw.clone(), true));
+                }
+            }
+        }
+
+        for (key, value, failed) in resolved {
+            self.inner.get_mut(&key).unwrap().value = value;
+
+            if failed {
+                self.failures.insert(key);
+            } else {
+                self.failures.remove(&key);
+            }
+        }
+
+        failure.map_or(Ok(()), Err)
+    }
+
+    /// Substitutes `${var}` references in a value, following Hadoop.
+    ///
+    /// References are replaced by raw values, which are then substituted
+    /// in turn. Substitution stops at the first reference which can't be
+    /// resolved, leaving it in place, and fails after too many nested
+    /// references.
+    fn substitute<F>(&self, value: &str, env: F) -> Result<String, Error>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut eval = value.to_owned();
+
+        for _ in 0..MAX_SUBSTITUTIONS {
+            let (start, end) = match find_reference(&eval) {
+                Some(bounds) => bounds,
+                None => return Ok(eval),
+            };
+
+            let var = &eval[start + 2..end - 1];
+
+            let val = match var.strip_prefix("env.").filter(|v| !v.is_empty()) {
+                Some(var) => lookup_env(var, &env),
+                None => self.entry(var).map(|entry| entry.raw.clone()),
+            };
+
+            // unresolved, or a reference to itself
+            let val = match val {
+                Some(val) if val != eval[start..end] => val,
+                _ => return Ok(eval),
+            };
 
-        // hadoop compatibility
-        if key_str.contains('.') {
-            key_str = key_str.replace(".", "_");
+            eval.replace_range(start..end, &val);
         }
 
-        // insert into the internal mapping
-        self.inner.insert(key_str, val.into());
+        Err(Error::Config(format!(
+            "variable substitution depth too large: {}",
+            value
+        )))
+    }
+
+    /// Parses a trimmed `Configuration` value, if present.
+    fn parse<T, F>(&self, key: &str, expected: &str, parser: F) -> Result<Option<T>, Error>
+    where
+        F: FnOnce(&str) -> Option<T>,
+    {
+        match self.get(key) {
+            None => Ok(None),
+            Some(val) => match parser(val.trim()) {
+                Some(parsed) => Ok(Some(parsed)),
+                None => Err(Error::Config(format!(
+                    "expected {} for {}, found {:?}",
+                    expected, key, val
+                ))),
+            },
+        }
+    }
+}
+
+/// The maximum number of substitutions made in a single value.
+const MAX_SUBSTITUTIONS: usize = 20;
+
+/// Converts a Hadoop key into the form exported by Hadoop Streaming.
+fn mangle(key: &str) -> String {
+    key.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// Finds the bounds of the first `${var}` reference in a value.
+///
+/// Variable names can't contain whitespace, `$`, `{` or `}`.
+fn find_reference(value: &str) -> Option<(usize, usize)> {
+    let mut offset = 0;
+
+    while let Some(found) = value[offset..].find("${") {
+        let start = offset + found;
+        let name = &value[start + 2..];
+        let len = name
+            .find(|c: char| c.is_whitespace() || c == '$' || c == '{' || c == '}')
+            .unwrap_or(name.len());
+
+        if len > 0 && name[len..].starts_with('}') {
+            return Some((start, start + 2 + len + 1));
+        }
+
+        offset = start + 2;
+    }
+
+    None
+}
+
+/// Resolves an environment variable reference, with optional defaults.
+///
+/// As in Hadoop, `VAR:-default` uses the default when `VAR` is unset or
+/// empty, whereas `VAR-default` only uses it when `VAR` is unset.
+fn lookup_env<F>(var: &str, lookup: F) -> Option<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    for (idx, c) in var.char_indices() {
+        if c == ':' && var[idx + 1..].starts_with('-') {
+            let default = &var[idx + 2..];
+            return match lookup(&var[..idx]) {
+                Some(val) if !val.is_empty() => Some(val),
+                _ => Some(default.to_owned()),
+            };
+        }
+
+        if c == '-' {
+            let default = &var[idx + 1..];
+            return lookup(&var[..idx]).or_else(|| Some(default.to_owned()));
+        }
+    }
+
+    lookup(var)
+}
+
+/// Creates an `Error` for a missing `Configuration` value.
+fn missing(key: &str) -> Error {
+    Error::Config(format!("missing required value for {}", key))
+}
+
+/// Parses a `bool` using Hadoop's rules.
+fn parse_bool(val: &str) -> Option<bool> {
+    if val.eq_ignore_ascii_case("true") {
+        Some(true)
+    } else if val.eq_ignore_ascii_case("false") {
+        Some(false)
+    } else {
+        None
     }
 }
 
+/// Parses a comma separated list, dropping empty entries.
+fn parse_list(val: &str) -> Vec<&str> {
+    val.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .collect()
+}
+
+/// Parses a number of bytes with an optional binary suffix.
+fn parse_bytes(val: &str) -> Option<u64> {
+    let split = val.len() - val.ends_with(|c: char| c.is_ascii_alphabetic()) as usize;
+    let (amount, suffix) = val.split_at(split);
+
+    let shift = match suffix.to_ascii_lowercase().as_str() {
+        "" => 0,
+        "k" => 10,
+        "m" => 20,
+        "g" => 30,
+        "t" => 40,
+        "p" => 50,
+        "e" => 60,
+        _ => return None,
+    };
+
+    amount.trim().parse::<u64>().ok()?.checked_mul(1 << shift)
+}
+
+/// Parses a `Duration` with an optional unit suffix.
+fn parse_duration(val: &str) -> Option<Duration> {
+    let split = val
+        .find(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(val.len());
+
+    let (amount, unit) = val.split_at(split);
+    let amount = amount.trim().parse::<u64>().ok()?;
+
+    let nanos = match unit.to_ascii_lowercase().as_str() {
+        "ns" => 1,
+        "us" => 1_000,
+        "" | "ms" => 1_000_000,
+        "s" => 1_000_000_000,
+        "m" => 60_000_000_000,
+        "h" => 3_600_000_000_000,
+        "d" => 86_400_000_000_000,
+        _ => return None,
+    };
+
+    amount.checked_mul(nanos).map(Duration::from_nanos)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -87,13 +575,19 @@ mod tests {
             ("FAKE_VAR", "1"),
             ("mapred.job.id", "123"),
             ("mapred_job_id", "123"),
+            ("mapreduce_job_reduce_slowstart_completedMaps", "0.05"),
         ];
 
         let conf = Configuration::with_env(env.into_iter());
 
-        assert_eq!(conf.inner.get("FAKE_VAR"), None);
-        assert_eq!(conf.inner.get("mapred.job.id"), None);
-        assert_eq!(conf.inner.get("mapred_job_id"), Some(&"123".to_owned()));
+        assert!(!conf.inner.contains_key("FAKE_VAR"));
+        assert!(conf.inner.contains_key("mapred.job.id"));
+        assert!(conf.inner.contains_key("mapred_job_id"));
+        assert_eq!(
+            conf.get("mapreduce.job.reduce.slowstart.completedMaps"),
+            Some("0.05")
+        );
+        assert_eq!(conf.source("mapred.job.id"), Some(&Source::Env));
     }
 
     #[test]
@@ -113,5 +607,290 @@ mod tests {
         conf.insert("mapred.job.id", "123");
 
         assert_eq!(conf.get("mapred_job_id"), Some("123"));
+        assert_eq!(conf.source("mapred.job.id"), Some(&Source::Programmatic));
+    }
+
+    #[test]
+    fn test_lossless_keys() {
+        let mut conf = Configuration::default();
+
+        conf.insert("a.b_c", "1");
+        conf.insert("a_b.c", "2");
+
+        assert_eq!(conf.get("a.b_c"), Some("1"));
+        assert_eq!(conf.get("a_b.c"), Some("2"));
+        assert_eq!(conf.get("a.b.c"), None);
+        assert_eq!(conf.get("a_b_c"), Some("2"));
+
+        let mut keys = conf.iter().map(|(key, _)| key).collect::<Vec<_>>();
+        keys.sort();
+
+        assert_eq!(keys, vec!["a.b_c", "a_b.c"]);
+    }
+
+    #[test]
+    fn test_source_priority() {
+        let mut conf = Configuration::default();
+
+        conf.load_xml(
+            "<configuration>
+              <property><name>mapreduce.job.id</name><value>xml</value></property>
+              <property><name>mapreduce.job.name</name><value>xml</value></property>
+            </configuration>",
+        )
+        .unwrap();
+
+        conf.extend_env(vec![("mapreduce_job_id", "env")].into_iter());
+
+        assert_eq!(conf.get("mapreduce.job.id"), Some("env"));
+        assert_eq!(conf.source("mapreduce.job.id"), Some(&Source::Env));
+        assert_eq!(conf.get("mapreduce.job.name"), Some("xml"));
+        assert_eq!(conf.source("mapreduce_job_name"), Some(&Source::Xml(None)));
+
+        conf.insert("mapreduce.job.id", "programmatic");
+
+        assert_eq!(conf.get("mapreduce.job.id"), Some("programmatic"));
+        assert_eq!(conf.get("mapreduce_job_id"), Some("programmatic"));
+    }
+
+    #[test]
+    fn test_xml_loading() {
+        let mut conf = Configuration::default();
+
+        conf.insert("mapred.job.id", "123");
+        conf.load_xml(
+            r#"<?xml version="1.0"?>
+            <configuration>
+              <property>
+                <name>mapred.job.id</name>
+                <value>456</value>
+              </property>
+              <property>
+                <name> efflux.final </name>
+                <value>first</value>
+                <final>true</final>
+              </property>
+              <property>
+                <name>efflux.missing.value</name>
+              </property>
+            </configuration>"#,
+        )
+        .unwrap();
+
+        assert_eq!(conf.get("mapred.job.id"), Some("456"));
+        assert_eq!(conf.get("efflux.final"), Some("first"));
+        assert_eq!(conf.get("efflux.missing.value"), None);
+
+        conf.load_xml(
+            "<configuration>
+              <property><name>efflux.final</name><value>second</value></property>
+            </configuration>",
+        )
+        .unwrap();
+
+        assert_eq!(conf.get("efflux.final"), Some("first"));
+    }
+
+    #[test]
+    fn test_xml_loading_errors() {
+        let mut conf = Configuration::default();
+
+        assert!(conf.load_xml("<configuration>").is_err());
+        assert!(conf.load_xml("<properties/>").is_err());
+        assert!(conf.load("missing-site.xml").is_err());
+    }
+
+    #[test]
+    fn test_xml_file_loading() {
+        let path = env::temp_dir().join(format!("efflux-conf-{}.xml", std::process::id()));
+
+        fs::write(
+            &path,
+            "<configuration>
+              <property><name>mapred.job.id</name><value>123</value></property>
+            </configuration>",
+        )
+        .unwrap();
+
+        let mut conf = Configuration::default();
+        let result = conf.load(&path);
+
+        fs::remove_file(&path).unwrap();
+
+        assert!(result.is_ok());
+        assert_eq!(conf.get("mapred.job.id"), Some("123"));
+    }
+
+    #[test]
+    fn test_variable_substitution() {
+        let mut conf = Configuration::default();
+
+        conf.insert("efflux.base", "/tmp");
+        conf.load_xml(
+            "<configuration>
+              <property><name>efflux.dir</name><value>${efflux.base}/${efflux.name}</value></property>
+              <property><name>efflux.name</name><value>job</value></property>
+              <property><name>efflux.env</name><value>${env.EFFLUX_CONF_TEST}</value></property>
+              <property><name>efflux.default</name><value>${env.EFFLUX_CONF_UNSET:-fallback}</value></property>
+              <property><name>efflux.unbound</name><value>${efflux.unset}/${efflux.base}</value></property>
+              <property><name>efflux.invalid</name><value>${ efflux.base}</value></property>
+            </configuration>",
+        )
+        .unwrap();
+
+        let env = |name: &str| (name == "EFFLUX_CONF_TEST").then(|| "env".to_owned());
+        conf.resolve_with(env).unwrap();
+
+        assert_eq!(conf.get("efflux.dir"), Some("/tmp/job"));
+        assert_eq!(conf.get("efflux.env"), Some("env"));
+        assert_eq!(conf.get("efflux.default"), Some("fallback"));
+        assert_eq!(
+            conf.get("efflux.unbound"),
+            Some("${efflux.unset}/${efflux.base}")
+        );
+        assert_eq!(conf.get("efflux.invalid"), Some("${ efflux.base}"));
+
+        let result = conf.load_xml(
+            "<configuration>
+              <property><name>efflux.loop</name><value>x${efflux.loop}</value></property>
+            </configuration>",
+        );
+
+        assert!(result.is_err());
+        assert_eq!(conf.get("efflux.loop"), Some("x${efflux.loop}"));
+
+        // failures are only reported once
+        let result = conf.load_xml(
+            "<configuration>
+              <property><name>efflux.other</name><value>${efflux.name}</value></property>
+            </configuration>",
+        );
+
+        assert!(result.is_ok());
+        assert_eq!(conf.get("efflux.other"), Some("job"));
+        assert_eq!(conf.references.len(), 6);
+    }
+
+    #[test]
+    fn test_cross_source_substitution() {
+        let mut conf = Configuration::default();
+
+        conf.load_xml(
+            "<configuration>
+              <property><name>efflux.dir</name><value>${efflux.base}/job</value></property>
+              <property><name>efflux.name</name><value>job</value></property>
+            </configuration>",
+        )
+        .unwrap();
+
+        // xml values can reference values set later
+        assert_eq!(conf.get("efflux.dir"), Some("${efflux.base}/job"));
+
+        conf.extend_env(
+            vec![("efflux_base", "/tmp"), ("efflux_tag", "${efflux.name}")].into_iter(),
+        );
+
+        assert_eq!(conf.get("efflux.dir"), Some("/tmp/job"));
+        assert_eq!(conf.get("efflux.tag"), Some("job"));
+
+        conf.insert("efflux.output", "${efflux.dir}/out");
+        conf.insert("efflux.base", "/data");
+
+        assert_eq!(conf.get("efflux.dir"), Some("/data/job"));
+        assert_eq!(conf.get("efflux.output"), Some("/data/job/out"));
+
+        let mut keys = conf
+            .iter()
+            .filter(|(_, value)| value.starts_with("/data"))
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>();
+        keys.sort();
+
+        assert_eq!(keys, vec!["efflux.base", "efflux.dir", "efflux.output"]);
+    }
+
+    #[test]
+    fn test_env_lookup() {
+        let env = |name: &str| (name == "EFFLUX_CONF_EMPTY").then(String::new);
+
+        assert_eq!(lookup_env("EFFLUX_CONF_UNSET", env), None);
+        assert_eq!(lookup_env("EFFLUX_CONF_UNSET-a", env), Some("a".to_owned()));
+        assert_eq!(lookup_env("EFFLUX_CONF_EMPTY-a", env), Some("".to_owned()));
+        assert_eq!(
+            lookup_env("EFFLUX_CONF_EMPTY:-a", env),
+            Some("a".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_typed_retrieval() {
+        let mut conf = Configuration::default();
+
+        conf.insert("efflux.int", " 10 ");
+        conf.insert("efflux.bool", "TRUE");
+        conf.insert("efflux.list", "a, b,,c ");
+        conf.insert("efflux.bytes", "128m");
+        conf.insert("efflux.duration", "30s");
+        conf.insert("efflux.invalid", "nope");
+
+        assert_eq!(conf.get_parsed::<u32>("efflux.int"), Some(10));
+        assert_eq!(conf.get_parsed::<u32>("efflux.invalid"), None);
+        assert_eq!(conf.get_bool("efflux.bool"), Some(true));
+        assert_eq!(conf.get_bool("efflux.invalid"), None);
+        assert_eq!(conf.get_list("efflux.list"), Some(vec!["a", "b", "c"]));
+        assert_eq!(conf.get_bytes("efflux.bytes"), Some(134_217_728));
+        assert_eq!(conf.get_bytes("efflux.int"), Some(10));
+        assert_eq!(
+            conf.get_duration("efflux.duration"),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            conf.get_duration("efflux.int"),
+            Some(Duration::from_millis(10))
+        );
+        assert_eq!(conf.get_duration("efflux.missing"), None);
+    }
+
+    #[test]
+    fn test_value_parsing() {
+        assert_eq!(parse_bool("False"), Some(false));
+        assert_eq!(parse_bool("yes"), None);
+
+        assert_eq!(parse_list(""), Vec::<&str>::new());
+
+        assert_eq!(parse_bytes("1K"), Some(1024));
+        assert_eq!(parse_bytes("2 g"), Some(2 << 30));
+        assert_eq!(parse_bytes("1x"), None);
+        assert_eq!(parse_bytes("16e"), None);
+
+        assert_eq!(parse_duration("5ns"), Some(Duration::from_nanos(5)));
+        assert_eq!(parse_duration("2 h"), Some(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("1d"), Some(Duration::from_secs(86400)));
+        assert_eq!(parse_duration("1w"), None);
+        assert_eq!(parse_duration("ms"), None);
+    }
+
+    #[test]
+    fn test_required_retrieval() {
+        let mut conf = Configuration::default();
+
+        conf.insert("efflux.int", "10");
+        conf.insert("efflux.invalid", "nope");
+
+        assert_eq!(conf.require("efflux.int").unwrap(), "10");
+        assert_eq!(conf.require_parsed::<i64>("efflux.int").unwrap(), 10);
+        assert_eq!(
+            conf.require_parsed::<i64>("efflux.invalid")
+                .unwrap_err()
+                .to_string(),
+            "invalid configuration: expected a valid value for efflux.invalid, found \"nope\""
+        );
+        assert_eq!(
+            conf.require_bool("efflux.missing").unwrap_err().to_string(),
+            "invalid configuration: missing required value for efflux.missing"
+        );
+        assert!(conf.require_list("efflux.missing").is_err());
+        assert!(conf.require_bytes("efflux.invalid").is_err());
+        assert!(conf.require_duration("efflux.invalid").is_err());
     }
 }
@@ -0,0 +1,218 @@
+//! Counter bindings to aggregate counter updates of a stage.
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use super::{Configuration, Context, Reports};
+
+/// Counters structure to aggregate the counters of a stage in memory.
+///
+/// Every counter update sent to Hadoop is a line written to `stderr`,
+/// which is expensive when counting per record. Updates made via the
+/// `Context` are instead added up here, and only sent to Hadoop every
+/// `efflux.counters.flush.interval.ms` (defaulting to 10 seconds) and
+/// once the stage has finished.
+#[derive(Debug)]
+pub struct Counters {
+    groups: BTreeMap<String, BTreeMap<String, Value>>,
+    interval: Duration,
+    flushed: Instant,
+}
+
+/// Value structure to track the total and unsent amounts of a counter.
+#[derive(Debug, Default)]
+struct Value {
+    total: i64,
+    pending: i64,
+}
+
+impl Counters {
+    /// Creates a new `Counters` from a job `Configuration`.
+    pub fn new(conf: &Configuration) -> Self {
+        let interval = conf
+            .get_duration("efflux.counters.flush.interval.ms")
+            .unwrap_or(Duration::from_secs(10));
+
+        Self {
+            groups: BTreeMap::new(),
+            interval,
+            flushed: Instant::now(),
+        }
+    }
+
+    /// Retrieves the total amount of a counter, or zero if never updated.
+    pub fn get(&self, group: &str, label: &str) -> i64 {
+        self.groups
+            .get(group)
+            .and_then(|labels| labels.get(label))
+            .map(|value| value.total)
+            .unwrap_or(0)
+    }
+
+    /// Retrieves an iterator over all counters, sorted by group and label.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str, i64)> {
+        self.groups.iter().flat_map(|(group, labels)| {
+            labels
+                .iter()
+                .map(move |(label, value)| (&group[..], &label[..], value.total))
+        })
+    }
+
+    /// Adds an amount to a counter, validating the group and label.
+    ///
+    /// Hadoop splits counter updates on `","`, so neither the group nor
+    /// label may contain one (or a newline, which ends the update).
+    pub(crate) fn incr(&mut self, group: &str, label: &str, amount: i64) -> Result<(), String> {
+        for name in &[group, label] {
+            if name.is_empty() || name.contains([',', '\n']) {
+                return Err(format!("invalid counter name: {:?}", name));
+            }
+        }
+
+        let labels = match self.groups.get_mut(group) {
+            Some(labels) => labels,
+            None => self.groups.entry(group.to_owned()).or_default(),
+        };
+
+        let value = match labels.get_mut(label) {
+            Some(value) => value,
+            None => labels.entry(label.to_owned()).or_default(),
+        };
+
+        value.total += amount;
+        value.pending += amount;
+
+        Ok(())
+    }
+
+    /// Determines whether pending updates are due to be flushed.
+    #[inline]
+    pub(crate) fn due(&self) -> bool {
+        self.flushed.elapsed() >= self.interval
+    }
+
+    /// Determines whether any updates have yet to be sent to Hadoop.
+    #[cfg(test)]
+    pub(crate) fn pending(&self) -> bool {
+        self.groups
+            .values()
+            .any(|labels| labels.values().any(|value| value.pending != 0))
+    }
+
+    /// Sends all pending updates to Hadoop.
+    pub(crate) fn flush(&mut self) {
+        for (group, labels) in &mut self.groups {
+            for (label, value) in labels {
+                if value.pending != 0 {
+                    update_counter!(group, label, value.pending);
+                    value.pending = 0;
+                }
+            }
+        }
+        self.flushed = Instant::now();
+    }
+}
+
+impl Default for Counters {
+    /// Creates a new `Counters` using the default flush interval.
+    fn default() -> Self {
+        Self::new(&Configuration::default())
+    }
+}
+
+/// Counter structure to represent a single counter of a `Context`.
+///
+/// This is a handle created via `Context::counter`, allowing a counter
+/// to be updated without having to repeat the group and label.
+pub struct Counter<'c, 'a> {
+    ctx: &'c mut Context<'a>,
+    group: &'c str,
+    label: &'c str,
+}
+
+impl<'c, 'a> Counter<'c, 'a> {
+    /// Creates a new `Counter` handle against a `Context`.
+    pub(crate) fn new(ctx: &'c mut Context<'a>, group: &'c str, label: &'c str) -> Self {
+        Self { ctx, group, label }
+    }
+
+    /// Increments the counter by the provided amount.
+    ///
+    /// An invalid group or label fails the stage, as Hadoop would be
+    /// unable to parse the update.
+    pub fn incr(&mut self, amount: i64) {
+        let counters = match self.ctx.get_mut::<Counters>() {
+            Some(counters) => counters,
+            None => {
+                self.ctx.insert(Counters::default());
+                self.ctx.get_mut::<Counters>().unwrap()
+            }
+        };
+
+        if let Err(reason) = counters.incr(self.group, self.label, amount) {
+            self.ctx.fail(reason);
+            return;
+        }
+
+        if counters.due() {
+            counters.flush();
+        }
+
+        if let Some(reports) = self.ctx.get_mut::<Reports>() {
+            reports.record_counter(self.group.to_owned(), self.label.to_owned(), amount);
+        }
+    }
+
+    /// Retrieves the current total of the counter.
+    pub fn value(&self) -> i64 {
+        self.ctx
+            .get::<Counters>()
+            .map(|counters| counters.get(self.group, self.label))
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counter_aggregation() {
+        let mut counters = Counters::default();
+
+        counters.incr("group", "label", 1).unwrap();
+        counters.incr("group", "label", 2).unwrap();
+        counters.incr("another", "label", 1).unwrap();
+
+        assert_eq!(counters.get("group", "label"), 3);
+        assert_eq!(counters.get("group", "missing"), 0);
+        assert_eq!(
+            counters.iter().collect::<Vec<_>>(),
+            vec![("another", "label", 1), ("group", "label", 3)]
+        );
+
+        counters.flush();
+
+        // totals are kept after flushing
+        assert_eq!(counters.get("group", "label"), 3);
+        assert_eq!(counters.groups["group"]["label"].pending, 0);
+    }
+
+    #[test]
+    fn test_counter_validation() {
+        let mut counters = Counters::default();
+
+        assert!(counters.incr("group,name", "label", 1).is_err());
+        assert!(counters.incr("group", "label\n", 1).is_err());
+        assert!(counters.incr("", "label", 1).is_err());
+        assert_eq!(counters.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_counter_flush_interval() {
+        let mut conf = Configuration::default();
+        conf.insert("efflux.counters.flush.interval.ms", "0");
+
+        assert!(Counters::new(&conf).due());
+        assert!(!Counters::default().due());
+    }
+}
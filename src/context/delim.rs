@@ -1,13 +1,30 @@
 //! Delimiter bindings to provide byte offsets for all stages.
 use super::conf::Configuration;
+use crate::keys;
 
 /// Delimiters struct to store the input/output separators
 /// for all stages of a MapReduce lifecycle. Once created,
 /// this structure should be considered immutable.
-#[derive(Debug)]
+///
+/// Alongside the separators, this stores the number of fields
+/// which make up a key on either side of the stage. The input
+/// of a reduction stage uses the key fields of the mapping
+/// output, as that's the key the shuffle groups on.
+///
+/// The number of key fields is set by `stream.num.map.output.key.fields`
+/// and `stream.num.reduce.output.key.fields`, with separators set by the
+/// `stream.map.output.field.separator` family of keys. Reduction stages
+/// can instead group on a prefix of the key fields, via the first spec of
+/// `mapreduce.partition.keycomparator.options` when `KeyFieldBasedComparator`
+/// is the job comparator (i.e. `-k1,1 -k2,2nr` groups on the first field,
+/// sorting values on the second). Any remaining fields of the key are then
+/// passed as part of the value, allowing values to be sorted in a group.
+#[derive(Clone, Debug)]
 pub struct Delimiters {
     input: Vec<u8>,
     output: Vec<u8>,
+    input_fields: usize,
+    output_fields: usize,
 }
 
 impl Delimiters {
@@ -15,7 +32,7 @@ impl Delimiters {
     pub fn new(conf: &Configuration) -> Self {
         // check to see if this is map/reduce stage
         let stage = match conf.get("mapreduce.task.ismap") {
-            Some(val) if val == "true" => "map",
+            Some("true") => "map",
             _ => "reduce",
         };
 
@@ -23,13 +40,41 @@ impl Delimiters {
         let input_key = format!("stream.{}.input.field.separator", stage);
         let output_key = format!("stream.{}.output.field.separator", stage);
 
+        // map output key fields are used as both map output and reduce input
+        let map_fields = fields(conf, "stream.num.map.output.key.fields");
+
+        // fetch the number of key fields for the current stage
+        let (input_fields, output_fields) = match stage {
+            "map" => (1, map_fields),
+            _ => (
+                group_fields(conf, map_fields),
+                fields(conf, "stream.num.reduce.output.key.fields"),
+            ),
+        };
+
         Self {
             // separators are optional, so default to a tab
             input: conf.get(&input_key).unwrap_or("\t").as_bytes().to_vec(),
             output: conf.get(&output_key).unwrap_or("\t").as_bytes().to_vec(),
+            input_fields,
+            output_fields,
         }
     }
 
+    /// Creates a new `Delimiters` for a combiner, from a map `Configuration`.
+    ///
+    /// Combiners read the output of the mapping stage, so the input uses
+    /// the map output separator and groups on the map output key fields
+    /// (or a prefix of them, as a reduction would).
+    pub(crate) fn combiner(conf: &Configuration) -> Self {
+        let mut delim = Self::new(conf);
+        let map_fields = delim.output_fields;
+
+        delim.input = delim.output.clone();
+        delim.input_fields = group_fields(conf, map_fields);
+        delim
+    }
+
     /// Returns a reference to the input delimiter.
     #[inline]
     pub fn input(&self) -> &[u8] {
@@ -41,6 +86,88 @@ impl Delimiters {
     pub fn output(&self) -> &[u8] {
         &self.output
     }
+
+    /// Returns the number of fields in an input key.
+    #[inline]
+    pub fn input_fields(&self) -> usize {
+        self.input_fields
+    }
+
+    /// Returns the number of fields in an output key.
+    #[inline]
+    pub fn output_fields(&self) -> usize {
+        self.output_fields
+    }
+
+    /// Sets the number of fields in an input key, to group a reduction on.
+    #[inline]
+    pub(crate) fn group(&mut self, fields: usize) {
+        self.input_fields = fields.max(1);
+    }
+
+    /// Splits an input record into a key/value pair.
+    ///
+    /// The key is made up of the number of input key fields, and so
+    /// the record is split at the matching input delimiter. If there
+    /// are not enough delimiters the entire record is treated as the
+    /// key, and the value is left empty.
+    #[inline]
+    pub fn split_input<'a>(&self, input: &'a [u8]) -> (&'a [u8], &'a [u8]) {
+        split(input, &self.input, self.input_fields)
+    }
+
+    /// Splits an output record into a key/value pair.
+    ///
+    /// The key is made up of the number of output key fields, and so
+    /// the record is split at the matching output delimiter. If there
+    /// are not enough delimiters the entire record is treated as the
+    /// key, and the value is left empty.
+    #[inline]
+    pub fn split_output<'a>(&self, output: &'a [u8]) -> (&'a [u8], &'a [u8]) {
+        split(output, &self.output, self.output_fields)
+    }
+}
+
+/// Parses a number of key fields from a `Configuration`, defaulting to 1.
+fn fields(conf: &Configuration, key: &str) -> usize {
+    conf.get(key)
+        .and_then(|fields| fields.trim().parse().ok())
+        .filter(|fields| *fields > 0)
+        .unwrap_or(1)
+}
+
+/// Determines the number of key fields a reduction groups on.
+///
+/// This is a prefix of the map output key fields, if grouping is set.
+fn group_fields(conf: &Configuration, map_fields: usize) -> usize {
+    keys::grouping(conf)
+        .map(|fields| fields.min(map_fields))
+        .unwrap_or(map_fields)
+}
+
+/// Splits a record into a key/value pair at the delimiter ending the key fields.
+#[inline]
+fn split<'a>(record: &'a [u8], delim: &[u8], fields: usize) -> (&'a [u8], &'a [u8]) {
+    let mut offset = 0;
+
+    for field in 1..=fields {
+        // search (quickly) for the next byte delimiter
+        match twoway::find_bytes(&record[offset..], delim) {
+            // split the record at the final key delimiter
+            Some(n) if field == fields => {
+                let n = offset + n;
+                return (&record[..n], &record[n + delim.len()..]);
+            }
+
+            // skip past any earlier key delimiters
+            Some(n) => offset += n + delim.len(),
+
+            // otherwise the record is the key
+            None => break,
+        }
+    }
+
+    (record, &b""[..])
 }
 
 #[cfg(test)]
@@ -77,6 +204,93 @@ mod tests {
         assert_eq!(delim.output(), b"|");
     }
 
+    #[test]
+    fn test_delimiter_splitting() {
+        let env = vec![
+            ("mapreduce.task.ismap", "true"),
+            ("stream.map.input.field.separator", ":"),
+            ("stream.map.output.field.separator", "||"),
+        ];
+
+        let conf = Configuration::with_env(env.into_iter());
+        let delim = Delimiters::new(&conf);
+
+        assert_eq!(
+            delim.split_input(b"key:val:ue"),
+            (&b"key"[..], &b"val:ue"[..])
+        );
+        assert_eq!(delim.split_input(b"key"), (&b"key"[..], &b""[..]));
+        assert_eq!(
+            delim.split_output(b"key||value"),
+            (&b"key"[..], &b"value"[..])
+        );
+        assert_eq!(delim.split_output(b"key||"), (&b"key"[..], &b""[..]));
+    }
+
+    #[test]
+    fn test_map_key_fields() {
+        let env = vec![
+            ("mapreduce.task.ismap", "true"),
+            ("stream.map.output.field.separator", "."),
+            ("stream.num.map.output.key.fields", "2"),
+            ("stream.num.reduce.output.key.fields", "3"),
+        ];
+
+        let conf = Configuration::with_env(env.into_iter());
+        let delim = Delimiters::new(&conf);
+
+        assert_eq!(delim.input_fields(), 1);
+        assert_eq!(delim.output_fields(), 2);
+
+        assert_eq!(delim.split_output(b"a.b.c.d"), (&b"a.b"[..], &b"c.d"[..]));
+        assert_eq!(delim.split_output(b"a.b."), (&b"a.b"[..], &b""[..]));
+        assert_eq!(delim.split_output(b"a.b"), (&b"a.b"[..], &b""[..]));
+        assert_eq!(delim.split_output(b"a"), (&b"a"[..], &b""[..]));
+    }
+
+    #[test]
+    fn test_reduce_key_fields() {
+        let env = vec![
+            ("mapreduce.task.ismap", "false"),
+            ("stream.num.map.output.key.fields", "2"),
+            ("stream.num.reduce.output.key.fields", "3"),
+        ];
+
+        let conf = Configuration::with_env(env.into_iter());
+        let delim = Delimiters::new(&conf);
+
+        assert_eq!(delim.input_fields(), 2);
+        assert_eq!(delim.output_fields(), 3);
+
+        assert_eq!(delim.split_input(b"a\tb\tc"), (&b"a\tb"[..], &b"c"[..]));
+        assert_eq!(
+            delim.split_output(b"a\tb\tc\td"),
+            (&b"a\tb\tc"[..], &b"d"[..])
+        );
+    }
+
+    #[test]
+    fn test_reduce_group_fields() {
+        let env = vec![
+            ("mapreduce.task.ismap", "false"),
+            ("stream.num.map.output.key.fields", "3"),
+            ("mapreduce.partition.keycomparator.options", "-k1,1 -k3,3nr"),
+            (
+                "mapreduce.job.output.key.comparator.class",
+                "org.apache.hadoop.mapreduce.lib.partition.KeyFieldBasedComparator",
+            ),
+        ];
+
+        let conf = Configuration::with_env(env.into_iter());
+        let delim = Delimiters::new(&conf);
+
+        assert_eq!(delim.input_fields(), 1);
+        assert_eq!(
+            delim.split_input(b"a\tb\tc\td"),
+            (&b"a"[..], &b"b\tc\td"[..])
+        );
+    }
+
     #[test]
     fn test_delimiter_defaults() {
         let env = Vec::<(String, String)>::new();
@@ -86,5 +300,7 @@ mod tests {
 
         assert_eq!(delim.input(), b"\t");
         assert_eq!(delim.output(), b"\t");
+        assert_eq!(delim.input_fields(), 1);
+        assert_eq!(delim.output_fields(), 1);
     }
 }
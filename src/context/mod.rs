@@ -46,63 +46,126 @@
 //! current set of `Contextual` types added are as follows:
 //!
 //! - `Configuration`
+//! - `Counters`
 //! - `Delimiters`
+//! - `DistributedCache`
+//! - `InputSplit`
 //! - `Offset`
+//! - `Reports` (only when testing via the `testing` module)
+//! - `TaskInfo`
 //!
 //! The most interesting of these types is the `Configuration` type, as it
 //! represents the job configuration provided by Hadoop.
 use std::any::{Any, TypeId};
 use std::collections::HashMap;
-use std::fmt::Display;
-use std::io::{self, Write};
+use std::fmt::{self, Debug, Display};
+use std::io::{self, BufWriter, Write};
 
+use crate::error::Error;
+use crate::io::typedbytes::Value;
+use crate::io::Format;
+
+mod cache;
 mod conf;
+mod counters;
 mod delim;
 mod offset;
+mod report;
+mod split;
+mod task;
 
-pub use self::conf::Configuration;
+pub use self::cache::{CacheFile, DistributedCache};
+pub use self::conf::{Configuration, Source};
+pub use self::counters::{Counter, Counters};
 pub use self::delim::Delimiters;
 pub use self::offset::Offset;
+pub use self::report::Reports;
+pub use self::split::InputSplit;
+pub use self::task::TaskInfo;
 
 /// Marker trait to represent types which can be added to a `Context`.
 pub trait Contextual: Any {}
 
 // all internal contextual types
 impl Contextual for Configuration {}
+impl Contextual for Counters {}
 impl Contextual for Delimiters {}
+impl Contextual for DistributedCache {}
+impl Contextual for InputSplit {}
 impl Contextual for Offset {}
+impl Contextual for Reports {}
+impl Contextual for TaskInfo {}
 
 /// Context structure to represent a Hadoop job context.
 ///
 /// This acts as an arbitrarily-typed bag, allowing for easy storage
 /// of random types between iterations of the stage. See the module
 /// documentation for further details and examples.
-#[derive(Debug, Default)]
-pub struct Context {
+///
+/// Each `Context` also owns the output sink of the stage, which is
+/// where all pairs passed to `write` will end up. This defaults to
+/// `io::stdout`, but can be any `Write` implementation. Pairs are
+/// written using the output `Format` of the job `Configuration`.
+///
+/// Output is buffered, and flushed once the stage has finished (or
+/// when the `Context` is dropped). A failed write fails the stage,
+/// so that no further input is read after the output has gone away.
+pub struct Context<'a> {
     data: HashMap<TypeId, Box<dyn Any>>,
+    output: BufWriter<Box<dyn Write + 'a>>,
+    format: Format,
+    failure: Option<Error>,
 }
 
-impl Context {
-    /// Creates a new `Context`.
+impl Context<'static> {
+    /// Creates a new `Context` writing to `io::stdout`.
     pub fn new() -> Self {
+        Self::with_output(io::stdout())
+    }
+}
+
+impl<'a> Context<'a> {
+    /// Creates a new `Context` writing to a custom output.
+    pub fn with_output<W>(output: W) -> Self
+    where
+        W: Write + 'a,
+    {
+        Self::with_config(Configuration::new(), output)
+    }
+
+    /// Creates a new `Context` from a job `Configuration` and custom output.
+    pub fn with_config<W>(conf: Configuration, output: W) -> Self
+    where
+        W: Write + 'a,
+    {
         // new base container
         let mut ctx = Self {
             data: HashMap::new(),
+            output: BufWriter::new(Box::new(output)),
+            format: Format::output(&conf),
+            failure: None,
         };
 
         // construct default types
-        let conf = Configuration::new();
         let delim = Delimiters::new(&conf);
+        let counters = Counters::new(&conf);
+        let task = TaskInfo::new(&conf);
+        let cache = DistributedCache::new(&conf);
+        let split = InputSplit::new(&conf);
 
-        // add both
+        // add all
         ctx.insert(conf);
         ctx.insert(delim);
+        ctx.insert(counters);
+        ctx.insert(task);
+        ctx.insert(cache);
+        ctx.insert(split);
 
         ctx
     }
 
     /// Retrieves a potential reference to a `Contextual` type.
-    pub fn get<T: Contextual>(&self) -> Option<&T>
+    pub fn get<T>(&self) -> Option<&T>
     where
         T: Contextual,
     {
@@ -143,20 +206,38 @@ impl Context {
     }
 
     /// Writes a key/value pair to the stage output.
+    ///
+    /// When the output `Format` is typed bytes, both the key and value
+    /// must already be encoded (see `write_typed`); anything else fails
+    /// the stage rather than corrupting the output. Raw bytes output is
+    /// framed automatically, so keys and values may contain any bytes.
+    ///
+    /// If the write fails, the stage is failed with the error and all
+    /// further writes are ignored; use `try_write` to handle the error.
     #[inline]
     pub fn write(&mut self, key: &[u8], val: &[u8]) {
-        // grab a reference to the context output delimiters
-        let out = self.get::<Delimiters>().unwrap().output();
+        if self.failed() {
+            return;
+        }
 
-        // lock the stdout buffer
-        let stdout = io::stdout();
-        let mut lock = stdout.lock();
+        if let Err(err) = self.try_write(key, val) {
+            self.failure = Some(Error::Write(err));
+        }
+    }
 
-        // write the pair and newline
-        lock.write_all(key).unwrap();
-        lock.write_all(out).unwrap();
-        lock.write_all(val).unwrap();
-        lock.write_all(b"\n").unwrap();
+    /// Writes a key/value pair to the stage output, returning any error.
+    #[inline]
+    pub fn try_write(&mut self, key: &[u8], val: &[u8]) -> io::Result<()> {
+        // grab a reference to the context output delimiters; this is done
+        // directly against the data map to allow borrowing the output sink
+        let delim = self
+            .data
+            .get(&TypeId::of::<Delimiters>())
+            .and_then(|b| b.downcast_ref::<Delimiters>())
+            .unwrap();
+
+        // write the pair in the output format
+        self.format.write(&mut self.output, delim, key, val)
     }
 
     /// Writes a key/value formatted pair to the stage output.
@@ -171,6 +252,118 @@ impl Context {
     {
         self.write(key.to_string().as_bytes(), val.to_string().as_bytes());
     }
+
+    /// Writes a key/value typed bytes pair to the stage output.
+    ///
+    /// This is a simple sugar API around `write` which encodes both the
+    /// key and value, for use when the output `Format` is typed bytes.
+    #[inline]
+    pub fn write_typed(&mut self, key: &Value, val: &Value) {
+        self.write(&key.encode(), &val.encode());
+    }
+
+    /// Retrieves a counter for the current job, by group and label.
+    ///
+    /// Updates are aggregated via the `Counters` of the `Context`, rather
+    /// than being sent to Hadoop immediately as with `update_counter!`.
+    /// This makes it cheap to update a counter for every record:
+    ///
+    /// ```rust
+    /// # extern crate efflux;
+    /// # use efflux::prelude::*;
+    /// # let mut ctx = Context::new();
+    /// ctx.counter("records", "total").incr(1);
+    /// ```
+    ///
+    /// Updates are also recorded against any `Reports` in the `Context`.
+    pub fn counter<'c>(&'c mut self, group: &'c str, label: &'c str) -> Counter<'c, 'a> {
+        Counter::new(self, group, label)
+    }
+
+    /// Updates the status for the current job.
+    ///
+    /// This logs the update in the same way as `update_status!`, whilst
+    /// also recording it against any `Reports` stored in the `Context`.
+    pub fn update_status<S>(&mut self, status: S)
+    where
+        S: Display,
+    {
+        update_status!(status);
+
+        if let Some(reports) = self.get_mut::<Reports>() {
+            reports.record_status(status.to_string());
+        }
+    }
+
+    /// Marks the current task as failed, with the reason for failure.
+    ///
+    /// No further input is provided to the stage once it has failed, and
+    /// the task exits with a non-zero status after logging the reason.
+    /// Only the first failure is kept, as later failures tend to be a
+    /// consequence of the first.
+    pub fn fail<E>(&mut self, reason: E)
+    where
+        E: Display,
+    {
+        self.fail_with(Error::Failed(reason.to_string()));
+    }
+
+    /// Marks the current task as failed with an `Error`.
+    pub(crate) fn fail_with(&mut self, err: Error) {
+        if self.failure.is_none() {
+            self.failure = Some(err);
+        }
+    }
+
+    /// Determines whether the current task has been marked as failed.
+    #[inline]
+    pub fn failed(&self) -> bool {
+        self.failure.is_some()
+    }
+
+    /// Takes the error the current task failed with, if it has failed.
+    pub(crate) fn take_failure(&mut self) -> Option<Error> {
+        self.failure.take()
+    }
+
+    /// Flushes any buffered pairs through to the stage output.
+    ///
+    /// This happens automatically once the stage has finished, and so is
+    /// only needed when output must be visible sooner. As with `write`,
+    /// an error fails the stage. Any pending counter updates are also
+    /// sent to Hadoop.
+    #[inline]
+    pub fn flush(&mut self) {
+        if let Some(counters) = self.get_mut::<Counters>() {
+            counters.flush();
+        }
+
+        if self.failed() {
+            return;
+        }
+
+        if let Err(err) = self.output.flush() {
+            self.failure = Some(Error::Write(err));
+        }
+    }
+}
+
+impl Debug for Context<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Context").field("data", &self.data).finish()
+    }
+}
+
+impl Default for Context<'static> {
+    /// Creates an empty `Context` writing to `io::stdout`.
+    fn default() -> Self {
+        Self {
+            data: HashMap::new(),
+            output: BufWriter::new(Box::new(io::stdout())),
+            format: Format::Text,
+            failure: None,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -228,6 +421,117 @@ mod tests {
         assert!(take.is_none());
     }
 
+    #[test]
+    fn test_custom_output() {
+        let mut output = Vec::new();
+
+        {
+            let mut ctx = Context::with_output(&mut output);
+
+            ctx.write(b"key", b"value");
+            ctx.write_fmt("number", 1);
+        }
+
+        assert_eq!(output, b"key\tvalue\nnumber\t1\n");
+    }
+
+    #[test]
+    fn test_typed_output() {
+        let mut conf = Configuration::default();
+        conf.insert("stream.reduce.output", "typedbytes");
+
+        let mut output = Vec::new();
+
+        {
+            let mut ctx = Context::with_config(conf, &mut output);
+
+            ctx.write_typed(&Value::Int(1), &Value::from("one"));
+            ctx.write(b"2", b"two");
+
+            assert!(matches!(ctx.take_failure(), Some(Error::Write(_))));
+        }
+
+        assert_eq!(output, b"\x03\0\0\0\x01\x07\0\0\0\x03one");
+    }
+
+    #[test]
+    fn test_reporting() {
+        let mut ctx = Context::new();
+
+        // updates without reports are only aggregated
+        ctx.counter("group", "label").incr(1);
+
+        ctx.insert(Reports::new());
+        ctx.counter("group", "label").incr(2);
+        ctx.update_status("done");
+
+        let reports = ctx.get::<Reports>().unwrap();
+
+        assert_eq!(reports.counter("group", "label"), 2);
+        assert_eq!(reports.status(), Some("done"));
+
+        assert_eq!(ctx.counter("group", "label").value(), 3);
+        assert_eq!(ctx.get::<Counters>().unwrap().get("group", "label"), 3);
+    }
+
+    #[test]
+    fn test_invalid_counter() {
+        let mut ctx = Context::new();
+
+        ctx.counter("group,name", "label").incr(1);
+
+        assert!(ctx.failed());
+        assert_eq!(ctx.counter("group,name", "label").value(), 0);
+    }
+
+    #[test]
+    fn test_context_failure() {
+        let mut ctx = Context::new();
+
+        assert!(!ctx.failed());
+
+        ctx.fail("first");
+        ctx.fail("second");
+
+        assert!(ctx.failed());
+        assert_eq!(
+            ctx.take_failure().unwrap().to_string(),
+            "task failed: first"
+        );
+        assert!(!ctx.failed());
+    }
+
+    #[test]
+    fn test_write_failure() {
+        let mut ctx = Context::with_output(TestOutput);
+
+        assert!(ctx.try_write(b"key", b"value").is_ok());
+        assert!(!ctx.failed());
+
+        // errors surface once the buffer is flushed
+        ctx.flush();
+
+        assert!(ctx.failed());
+
+        let failure = ctx.take_failure().unwrap();
+
+        assert!(failure.is_broken_pipe());
+        assert_eq!(failure.to_string(), "unable to write output: closed");
+    }
+
     struct TestStruct(usize);
     impl Contextual for TestStruct {}
+
+    /// Output which is always closed.
+    struct TestOutput;
+
+    impl Write for TestOutput {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
 }
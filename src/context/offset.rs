@@ -14,7 +14,18 @@ impl Offset {
         Offset(0)
     }
 
-    /// Shifts the inner offset by the provided shift value.Reducer
+    /// Creates a new `Offset` from the provided index.
+    pub fn with_start(start: usize) -> Offset {
+        Offset(start)
+    }
+
+    /// Retrieves the current value of the offset.
+    #[inline]
+    pub fn get(&self) -> usize {
+        self.0
+    }
+
+    /// Shifts the inner offset by the provided shift value.
     ///
     /// The newly shifted offset is then returned, for convenience.
     #[inline]
@@ -40,4 +51,13 @@ mod tests {
         assert_eq!(two, 2);
         assert_eq!(ten, 10);
     }
+
+    #[test]
+    fn test_offset_start() {
+        let mut offset = Offset::with_start(100);
+
+        assert_eq!(offset.get(), 100);
+        assert_eq!(offset.shift(5), 105);
+        assert_eq!(offset.get(), 105);
+    }
 }
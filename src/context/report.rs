@@ -0,0 +1,83 @@
+//! Report bindings to record counter and status updates of a stage.
+use std::collections::BTreeMap;
+
+/// Reports structure to record the updates made by a stage.
+///
+/// When a `Reports` exists on a `Context`, all counter and status updates
+/// made via the `Context` are recorded here in addition to being sent to
+/// Hadoop. This is mainly useful when testing a stage, to check that the
+/// expected updates were made.
+#[derive(Debug, Default)]
+pub struct Reports {
+    counters: BTreeMap<(String, String), i64>,
+    statuses: Vec<String>,
+}
+
+impl Reports {
+    /// Creates a new, empty `Reports`.
+    pub fn new() -> Reports {
+        Reports::default()
+    }
+
+    /// Retrieves the total amount of a counter, or zero if never updated.
+    pub fn counter(&self, group: &str, label: &str) -> i64 {
+        self.counters
+            .get(&(group.to_owned(), label.to_owned()))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Retrieves an iterator over all counters, sorted by group and label.
+    pub fn counters(&self) -> impl Iterator<Item = (&str, &str, i64)> {
+        self.counters
+            .iter()
+            .map(|((group, label), amount)| (&group[..], &label[..], *amount))
+    }
+
+    /// Retrieves the most recent status, if any.
+    pub fn status(&self) -> Option<&str> {
+        self.statuses.last().map(|status| &status[..])
+    }
+
+    /// Retrieves all statuses, in the order they were set.
+    pub fn statuses(&self) -> &[String] {
+        &self.statuses
+    }
+
+    /// Records an update to a counter.
+    pub(crate) fn record_counter(&mut self, group: String, label: String, amount: i64) {
+        *self.counters.entry((group, label)).or_insert(0) += amount;
+    }
+
+    /// Records an update to the status.
+    pub(crate) fn record_status(&mut self, status: String) {
+        self.statuses.push(status);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_recording() {
+        let mut reports = Reports::new();
+
+        reports.record_counter("group".into(), "label".into(), 1);
+        reports.record_counter("group".into(), "label".into(), 2);
+        reports.record_counter("another".into(), "label".into(), 1);
+        reports.record_status("one".into());
+        reports.record_status("two".into());
+
+        assert_eq!(reports.counter("group", "label"), 3);
+        assert_eq!(reports.counter("group", "missing"), 0);
+
+        assert_eq!(
+            reports.counters().collect::<Vec<_>>(),
+            vec![("another", "label", 1), ("group", "label", 3)]
+        );
+
+        assert_eq!(reports.status(), Some("two"));
+        assert_eq!(reports.statuses(), &["one", "two"]);
+    }
+}
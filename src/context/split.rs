@@ -0,0 +1,100 @@
+//! Split bindings to describe the input of a mapping stage.
+use super::Configuration;
+
+/// InputSplit structure to represent the input of a mapping stage.
+///
+/// Hadoop Streaming provides the file, starting byte and length of the
+/// split being read by each map task. This allows mappers to tag their
+/// output by source file (e.g. for joins), and is used to start the key
+/// `Offset` of the mapping stage at the correct byte. All values will be
+/// missing outside of a mapping stage.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct InputSplit {
+    file: Option<String>,
+    start: usize,
+    length: Option<usize>,
+}
+
+impl InputSplit {
+    /// Creates a new `InputSplit` from a job `Configuration`.
+    pub fn new(conf: &Configuration) -> Self {
+        let file = conf
+            .get("mapreduce.map.input.file")
+            .or_else(|| conf.get("map.input.file"))
+            .filter(|file| !file.is_empty())
+            .map(str::to_owned);
+
+        let start = conf
+            .get_parsed("mapreduce.map.input.start")
+            .or_else(|| conf.get_parsed("map.input.start"))
+            .unwrap_or(0);
+
+        let length = conf
+            .get_parsed("mapreduce.map.input.length")
+            .or_else(|| conf.get_parsed("map.input.length"));
+
+        Self {
+            file,
+            start,
+            length,
+        }
+    }
+
+    /// Creates a new `InputSplit` for a section of a file.
+    pub fn with_file<F>(file: F, start: usize, length: usize) -> Self
+    where
+        F: Into<String>,
+    {
+        Self {
+            file: Some(file.into()),
+            start,
+            length: Some(length),
+        }
+    }
+
+    /// Retrieves the path of the file being read, if known.
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_deref()
+    }
+
+    /// Retrieves the byte offset the split starts at within the file.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Retrieves the length of the split in bytes, if known.
+    pub fn length(&self) -> Option<usize> {
+        self.length
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_creation() {
+        let env = vec![
+            ("mapreduce_map_input_file", "hdfs://nn/data/part-00000"),
+            ("mapreduce_map_input_start", "134217728"),
+            ("mapreduce_map_input_length", "1024"),
+        ];
+
+        let conf = Configuration::with_env(env.into_iter());
+        let split = InputSplit::new(&conf);
+
+        assert_eq!(split.file(), Some("hdfs://nn/data/part-00000"));
+        assert_eq!(split.start(), 134_217_728);
+        assert_eq!(split.length(), Some(1024));
+    }
+
+    #[test]
+    fn test_split_defaults() {
+        let split = InputSplit::new(&Configuration::default());
+
+        assert_eq!(split, InputSplit::default());
+        assert_eq!(split.file(), None);
+        assert_eq!(split.start(), 0);
+        assert_eq!(split.length(), None);
+    }
+}
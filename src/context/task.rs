@@ -0,0 +1,273 @@
+//! Task bindings to describe the identity of the running task.
+use std::env;
+use std::path::{Path, PathBuf};
+
+use super::Configuration;
+
+/// TaskInfo structure to represent the identity of the current task.
+///
+/// Values are read from the job `Configuration`, checking the newer
+/// Hadoop keys before the deprecated `mapred` keys. When running outside
+/// of Hadoop, defaults are provided which mirror a local job runner (the
+/// first attempt of the first task in `job_local_0001`).
+///
+/// When the stage isn't configured, it's taken from the stage being run
+/// once it starts; until then it's unknown, and both `is_map` and
+/// `is_reduce` return `false`.
+#[derive(Clone, Debug)]
+pub struct TaskInfo {
+    job_id: String,
+    task_id: String,
+    attempt_id: String,
+    attempt: usize,
+    partition: usize,
+    is_map: Option<bool>,
+    reducers: usize,
+    working_dir: PathBuf,
+}
+
+impl TaskInfo {
+    /// Creates a new `TaskInfo` from a job `Configuration`.
+    pub fn new(conf: &Configuration) -> Self {
+        let is_map = conf
+            .get_bool("mapreduce.task.ismap")
+            .or_else(|| conf.get_bool("mapred.task.is.map"));
+
+        let partition = conf
+            .get_parsed("mapreduce.task.partition")
+            .or_else(|| conf.get_parsed("mapred.task.partition"))
+            .unwrap_or(0);
+
+        let reducers = conf
+            .get_parsed("mapreduce.job.reduces")
+            .or_else(|| conf.get_parsed("mapred.reduce.tasks"))
+            .unwrap_or(1);
+
+        let job_id = lookup(conf, "mapreduce.job.id", "mapred.job.id")
+            .unwrap_or_else(|| "job_local_0001".to_owned());
+
+        // identifiers are derived from the job identifier when missing
+        let task_id = lookup(conf, "mapreduce.task.id", "mapred.tip.id")
+            .unwrap_or_else(|| derive("task", &job_id, is_map, partition));
+
+        let attempt_id = lookup(conf, "mapreduce.task.attempt.id", "mapred.task.id")
+            .unwrap_or_else(|| derive("attempt", &job_id, is_map, partition) + "_0");
+
+        // the attempt number is the final component of the identifier
+        let attempt = attempt_id
+            .rsplit('_')
+            .next()
+            .and_then(|attempt| attempt.parse().ok())
+            .unwrap_or(0);
+
+        let working_dir = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+
+        Self {
+            job_id,
+            task_id,
+            attempt_id,
+            attempt,
+            partition,
+            is_map,
+            reducers,
+            working_dir,
+        }
+    }
+
+    /// Sets the stage of the task, if it wasn't configured.
+    ///
+    /// This is called by each stage as it starts, and re-derives any
+    /// identifiers which weren't provided to include the stage.
+    pub(crate) fn stage(&mut self, is_map: bool) {
+        if self.is_map.is_some() {
+            return;
+        }
+
+        let task_id = derive("task", &self.job_id, None, self.partition);
+        let attempt_id = derive("attempt", &self.job_id, None, self.partition) + "_0";
+
+        self.is_map = Some(is_map);
+
+        // only identifiers which were derived are replaced
+        if self.task_id == task_id {
+            self.task_id = derive("task", &self.job_id, self.is_map, self.partition);
+        }
+
+        if self.attempt_id == attempt_id {
+            self.attempt_id = derive("attempt", &self.job_id, self.is_map, self.partition) + "_0";
+        }
+    }
+
+    /// Retrieves the identifier of the job, e.g. `job_1520000000000_0001`.
+    pub fn job_id(&self) -> &str {
+        &self.job_id
+    }
+
+    /// Retrieves the identifier of the task, shared across all attempts.
+    pub fn task_id(&self) -> &str {
+        &self.task_id
+    }
+
+    /// Retrieves the identifier of the current task attempt.
+    pub fn attempt_id(&self) -> &str {
+        &self.attempt_id
+    }
+
+    /// Retrieves the attempt number of the task, starting from `0`.
+    pub fn attempt(&self) -> usize {
+        self.attempt
+    }
+
+    /// Retrieves the partition number of the task, starting from `0`.
+    pub fn partition(&self) -> usize {
+        self.partition
+    }
+
+    /// Determines whether the task is running a mapping stage.
+    pub fn is_map(&self) -> bool {
+        self.is_map == Some(true)
+    }
+
+    /// Determines whether the task is running a reduction stage.
+    pub fn is_reduce(&self) -> bool {
+        self.is_map == Some(false)
+    }
+
+    /// Retrieves the number of reducers configured for the job.
+    pub fn reducers(&self) -> usize {
+        self.reducers
+    }
+
+    /// Retrieves the working directory of the task.
+    pub fn working_dir(&self) -> &Path {
+        &self.working_dir
+    }
+}
+
+/// Derives a task identifier from the job identifier and stage.
+///
+/// The stage is left out of the identifier while it's unknown.
+fn derive(prefix: &str, job_id: &str, is_map: Option<bool>, partition: usize) -> String {
+    let suffix = job_id.strip_prefix("job_").unwrap_or(job_id);
+
+    match is_map {
+        Some(true) => format!("{}_{}_m_{:06}", prefix, suffix, partition),
+        Some(false) => format!("{}_{}_r_{:06}", prefix, suffix, partition),
+        None => format!("{}_{}_{:06}", prefix, suffix, partition),
+    }
+}
+
+/// Looks up a value using a key and the deprecated alternative.
+fn lookup(conf: &Configuration, key: &str, deprecated: &str) -> Option<String> {
+    conf.get(key)
+        .or_else(|| conf.get(deprecated))
+        .map(str::trim)
+        .filter(|val| !val.is_empty())
+        .map(str::to_owned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::Context;
+
+    #[test]
+    fn test_task_info_creation() {
+        let env = vec![
+            ("mapreduce_job_id", "job_1520000000000_0042"),
+            ("mapreduce_task_id", "task_1520000000000_0042_r_000003"),
+            (
+                "mapreduce_task_attempt_id",
+                "attempt_1520000000000_0042_r_000003_2",
+            ),
+            ("mapreduce_task_partition", "3"),
+            ("mapreduce_task_ismap", "false"),
+            ("mapreduce_job_reduces", "8"),
+        ];
+
+        let conf = Configuration::with_env(env.into_iter());
+        let info = TaskInfo::new(&conf);
+
+        assert_eq!(info.job_id(), "job_1520000000000_0042");
+        assert_eq!(info.task_id(), "task_1520000000000_0042_r_000003");
+        assert_eq!(info.attempt_id(), "attempt_1520000000000_0042_r_000003_2");
+        assert_eq!(info.attempt(), 2);
+        assert_eq!(info.partition(), 3);
+        assert!(info.is_reduce());
+        assert_eq!(info.reducers(), 8);
+        assert_eq!(info.working_dir(), env::current_dir().unwrap());
+    }
+
+    #[test]
+    fn test_task_info_deprecated_keys() {
+        let env = vec![
+            ("mapred.job.id", "job_local_0007"),
+            ("mapred.task.is.map", "true"),
+            ("mapred.task.partition", "1"),
+            ("mapred.reduce.tasks", "0"),
+        ];
+
+        let conf = Configuration::with_env(env.into_iter());
+        let info = TaskInfo::new(&conf);
+
+        assert_eq!(info.job_id(), "job_local_0007");
+        assert_eq!(info.task_id(), "task_local_0007_m_000001");
+        assert_eq!(info.attempt_id(), "attempt_local_0007_m_000001_0");
+        assert!(info.is_map());
+        assert_eq!(info.reducers(), 0);
+    }
+
+    #[test]
+    fn test_task_info_defaults() {
+        let info = TaskInfo::new(&Configuration::default());
+
+        assert_eq!(info.job_id(), "job_local_0001");
+        assert_eq!(info.task_id(), "task_local_0001_000000");
+        assert_eq!(info.attempt_id(), "attempt_local_0001_000000_0");
+        assert_eq!(info.attempt(), 0);
+        assert_eq!(info.partition(), 0);
+        assert!(!info.is_map());
+        assert!(!info.is_reduce());
+        assert_eq!(info.reducers(), 1);
+    }
+
+    #[test]
+    fn test_task_info_stage() {
+        let mut info = TaskInfo::new(&Configuration::default());
+
+        info.stage(true);
+
+        assert!(info.is_map());
+        assert_eq!(info.task_id(), "task_local_0001_m_000000");
+        assert_eq!(info.attempt_id(), "attempt_local_0001_m_000000_0");
+
+        let env = vec![("mapreduce.task.ismap", "false")];
+        let conf = Configuration::with_env(env.into_iter());
+        let mut info = TaskInfo::new(&conf);
+
+        // a configured stage is never replaced
+        info.stage(true);
+
+        assert!(info.is_reduce());
+        assert_eq!(info.task_id(), "task_local_0001_r_000000");
+    }
+
+    #[test]
+    fn test_task_info_lifecycle_stage() {
+        let mut output = Vec::new();
+
+        crate::run_mapper_with(
+            &b"line\n"[..],
+            &mut output,
+            |_key: usize, _value: &[u8], ctx: &mut Context| {
+                let info = ctx.get::<TaskInfo>().unwrap();
+                assert!(info.is_map());
+                assert_eq!(info.task_id(), "task_local_0001_m_000000");
+                ctx.write(b"mapped", b"");
+            },
+        )
+        .unwrap();
+
+        assert_eq!(output, b"mapped\t\n");
+    }
+}
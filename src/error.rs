@@ -0,0 +1,50 @@
+//! Error types raised during the execution of a stage.
+use std::error;
+use std::fmt::{self, Display};
+use std::io;
+
+/// Error enum to represent the ways a stage can fail.
+#[derive(Debug)]
+pub enum Error {
+    /// A `Configuration` value was missing or invalid.
+    Config(String),
+    /// The stage was marked as failed, with the provided reason.
+    Failed(String),
+    /// The input of the stage could not be read.
+    Read(io::Error),
+    /// The output of the stage could not be written.
+    Write(io::Error),
+}
+
+impl Error {
+    /// Determines whether the output of the stage was closed early.
+    ///
+    /// This happens when the process reading the output exits before
+    /// all output has been written, e.g. when piping output to `head`.
+    pub fn is_broken_pipe(&self) -> bool {
+        match self {
+            Error::Write(err) => err.kind() == io::ErrorKind::BrokenPipe,
+            _ => false,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Config(reason) => write!(f, "invalid configuration: {}", reason),
+            Error::Failed(reason) => write!(f, "task failed: {}", reason),
+            Error::Read(err) => write!(f, "unable to read input: {}", err),
+            Error::Write(err) => write!(f, "unable to write output: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Config(_) | Error::Failed(_) => None,
+            Error::Read(err) | Error::Write(err) => Some(err),
+        }
+    }
+}
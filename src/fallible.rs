@@ -0,0 +1,503 @@
+//! Fallible stages, which return errors rather than panicking.
+//!
+//! This module offers the `TryMapper` and `TryReducer` traits, which mirror
+//! the `Mapper` and `Reducer` traits but return a `Result` from each hook.
+//! How a failed record is handled is decided by the `Policy` set in the
+//! `efflux.failure.policy` key of the job `Configuration`:
+//!
+//! - `fail` (the default) fails the task, logging the error.
+//! - `skip` skips the record, counting it as `efflux/SKIPPED_RECORDS`.
+//! - `deadletter` writes the record to a dead-letter output, counting it
+//!   as `efflux/DEAD_LETTER_RECORDS`.
+//!
+//! Dead-letter records are appended to the file named by
+//! `efflux.failure.deadletter.path`, framed in the input format of the
+//! stage (using the output separator for text). If no path is set, they're
+//! written to the task logs instead. Errors in the setup and
+//! cleanup hooks always fail the task, as there is no record to skip.
+//!
+//! Skipping a record does not retract any pairs already written for it, as
+//! the output is not buffered per record. Hooks which can fail part way
+//! through a record should do any fallible work before calling `write`.
+//!
+//! Fallible stages are adapted into a `Mapper` or `Reducer`, and so can
+//! be used anywhere the base traits are:
+//!
+//! ```rust,no_run
+//! # extern crate efflux;
+//! use efflux::fallible::{self, TryMapper};
+//! use efflux::prelude::*;
+//! use std::str::{self, Utf8Error};
+//!
+//! struct UppercaseMapper;
+//!
+//! // emit every value in uppercase, failing on invalid UTF-8
+//! impl TryMapper for UppercaseMapper {
+//!     type Error = Utf8Error;
+//!
+//!     fn map(&mut self, _key: usize, value: &[u8], ctx: &mut Context) -> Result<(), Utf8Error> {
+//!         ctx.write(str::from_utf8(value)?.to_uppercase().as_bytes(), b"");
+//!         Ok(())
+//!     }
+//! }
+//!
+//! efflux::run_mapper(fallible::mapper(UppercaseMapper));
+//! ```
+use std::fmt::Display;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+
+use crate::context::{Configuration, Context, Delimiters};
+use crate::io::typedbytes::Value;
+use crate::io::Format;
+use crate::mapper::Mapper;
+use crate::reducer::Reducer;
+
+/// Trait to represent a fallible mapping stage of MapReduce.
+pub trait TryMapper {
+    /// Type of error returned by the hooks of this `TryMapper`.
+    type Error: Display;
+
+    /// Setup handler for the current `TryMapper`.
+    fn setup(&mut self, _ctx: &mut Context) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Mapping handler for the current `TryMapper`.
+    fn map(&mut self, key: usize, value: &[u8], ctx: &mut Context) -> Result<(), Self::Error>;
+
+    /// Cleanup handler for the current `TryMapper`.
+    fn cleanup(&mut self, _ctx: &mut Context) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Enables raw functions to act as `TryMapper` types.
+impl<M, E> TryMapper for M
+where
+    M: FnMut(usize, &[u8], &mut Context) -> Result<(), E>,
+    E: Display,
+{
+    type Error = E;
+
+    /// Mapping handler by passing through the values to the inner closure.
+    #[inline]
+    fn map(&mut self, key: usize, value: &[u8], ctx: &mut Context) -> Result<(), E> {
+        self(key, value, ctx)
+    }
+}
+
+/// Trait to represent a fallible reduction stage of MapReduce.
+pub trait TryReducer {
+    /// Type of error returned by the hooks of this `TryReducer`.
+    type Error: Display;
+
+    /// Setup handler for the current `TryReducer`.
+    fn setup(&mut self, _ctx: &mut Context) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Reduction handler for the current `TryReducer`.
+    fn reduce(
+        &mut self,
+        key: &[u8],
+        values: &[&[u8]],
+        ctx: &mut Context,
+    ) -> Result<(), Self::Error>;
+
+    /// Cleanup handler for the current `TryReducer`.
+    fn cleanup(&mut self, _ctx: &mut Context) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Enables raw functions to act as `TryReducer` types.
+impl<R, E> TryReducer for R
+where
+    R: FnMut(&[u8], &[&[u8]], &mut Context) -> Result<(), E>,
+    E: Display,
+{
+    type Error = E;
+
+    /// Reduction handler by passing through the values to the inner closure.
+    #[inline]
+    fn reduce(&mut self, key: &[u8], values: &[&[u8]], ctx: &mut Context) -> Result<(), E> {
+        self(key, values, ctx)
+    }
+}
+
+/// Policy enum to represent how records which fail are handled.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Policy {
+    /// Fail the task on the first failed record.
+    #[default]
+    Fail,
+    /// Skip failed records, counting them.
+    ///
+    /// Any pairs written before the failure remain in the stage output.
+    Skip,
+    /// Route failed records to a dead-letter output, counting them.
+    ///
+    /// Any pairs written before the failure remain in the stage output.
+    DeadLetter,
+}
+
+impl Policy {
+    /// Creates a new `Policy` from a job `Configuration`.
+    pub fn new(conf: &Configuration) -> Self {
+        match conf.get("efflux.failure.policy") {
+            Some("skip") => Policy::Skip,
+            Some("deadletter") => Policy::DeadLetter,
+            _ => Policy::Fail,
+        }
+    }
+}
+
+/// Adapts a `TryMapper` into a `Mapper`.
+pub fn mapper<M>(mapper: M) -> impl Mapper
+where
+    M: TryMapper,
+{
+    TryMapperStage {
+        mapper,
+        failures: Failures::default(),
+    }
+}
+
+/// Adapts a `TryReducer` into a `Reducer`.
+pub fn reducer<R>(reducer: R) -> impl Reducer
+where
+    R: TryReducer,
+{
+    TryReducerStage {
+        reducer,
+        failures: Failures::default(),
+    }
+}
+
+/// Stage structure to represent a `TryMapper` as a `Mapper`.
+struct TryMapperStage<M> {
+    mapper: M,
+    failures: Failures,
+}
+
+impl<M> Mapper for TryMapperStage<M>
+where
+    M: TryMapper,
+{
+    fn setup(&mut self, ctx: &mut Context) {
+        self.failures = Failures::new(ctx);
+
+        if let Err(err) = self.mapper.setup(ctx) {
+            ctx.fail(err);
+        }
+    }
+
+    fn map(&mut self, key: usize, value: &[u8], ctx: &mut Context) {
+        if let Err(err) = self.mapper.map(key, value, ctx) {
+            let key = self.failures.offset(key);
+            self.failures.record(err, &[(&key, value)], ctx);
+        }
+    }
+
+    fn cleanup(&mut self, ctx: &mut Context) {
+        if let Err(err) = self.mapper.cleanup(ctx) {
+            ctx.fail(err);
+        }
+        self.failures.close(ctx);
+    }
+}
+
+/// Stage structure to represent a `TryReducer` as a `Reducer`.
+struct TryReducerStage<R> {
+    reducer: R,
+    failures: Failures,
+}
+
+impl<R> Reducer for TryReducerStage<R>
+where
+    R: TryReducer,
+{
+    fn setup(&mut self, ctx: &mut Context) {
+        self.failures = Failures::new(ctx);
+
+        if let Err(err) = self.reducer.setup(ctx) {
+            ctx.fail(err);
+        }
+    }
+
+    fn reduce(&mut self, key: &[u8], values: &[&[u8]], ctx: &mut Context) {
+        if let Err(err) = self.reducer.reduce(key, values, ctx) {
+            let records = values.iter().map(|value| (key, *value)).collect::<Vec<_>>();
+
+            self.failures.record(err, &records, ctx);
+        }
+    }
+
+    fn cleanup(&mut self, ctx: &mut Context) {
+        if let Err(err) = self.reducer.cleanup(ctx) {
+            ctx.fail(err);
+        }
+        self.failures.close(ctx);
+    }
+}
+
+/// Failures structure to apply a `Policy` to failed records.
+#[derive(Default)]
+struct Failures {
+    policy: Policy,
+    path: Option<String>,
+    format: Format,
+    delim: Option<Delimiters>,
+    output: Option<BufWriter<File>>,
+}
+
+impl Failures {
+    /// Creates a new `Failures` from the job `Configuration` of a `Context`.
+    fn new(ctx: &Context) -> Self {
+        let conf = ctx.get::<Configuration>();
+
+        Self {
+            policy: conf.map(Policy::new).unwrap_or_default(),
+            path: conf
+                .and_then(|conf| conf.get("efflux.failure.deadletter.path"))
+                .map(str::to_owned),
+            format: conf.map(Format::input).unwrap_or_default(),
+            delim: ctx.get::<Delimiters>().cloned(),
+            output: None,
+        }
+    }
+
+    /// Converts the offset key of a mapping stage into a record key.
+    ///
+    /// Offsets are encoded as a long for typed bytes, so that the record
+    /// can be read back as a typed bytes pair.
+    fn offset(&self, key: usize) -> Vec<u8> {
+        match self.format {
+            Format::TypedBytes => Value::Long(key as i64).encode(),
+            _ => key.to_string().into_bytes(),
+        }
+    }
+
+    /// Records the failure of a set of records, based on the `Policy`.
+    fn record<E>(&mut self, err: E, records: &[(&[u8], &[u8])], ctx: &mut Context)
+    where
+        E: Display,
+    {
+        match self.policy {
+            Policy::Fail => ctx.fail(err),
+            Policy::Skip => {
+                log!("efflux: skipping record: {}", err);
+                ctx.counter("efflux", "SKIPPED_RECORDS")
+                    .incr(records.len() as i64);
+            }
+            Policy::DeadLetter => {
+                if let Err(io) = self.write(records) {
+                    ctx.fail(format!("unable to write dead letter: {}", io));
+                    return;
+                }
+                ctx.counter("efflux", "DEAD_LETTER_RECORDS")
+                    .incr(records.len() as i64);
+            }
+        }
+    }
+
+    /// Writes a set of records to the dead-letter output.
+    fn write(&mut self, records: &[(&[u8], &[u8])]) -> io::Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => {
+                for (key, value) in records {
+                    log!(
+                        "efflux: dead letter: {}\t{}",
+                        String::from_utf8_lossy(key),
+                        String::from_utf8_lossy(value)
+                    );
+                }
+                return Ok(());
+            }
+        };
+
+        // the output is only created on the first failure
+        if self.output.is_none() {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            self.output = Some(BufWriter::new(file));
+        }
+
+        let output = self.output.as_mut().unwrap();
+        let delim = self
+            .delim
+            .get_or_insert_with(|| Delimiters::new(&Configuration::default()));
+
+        for (key, value) in records {
+            self.format.write(output, delim, key, value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Closes the dead-letter output, flushing any buffered records.
+    fn close(&mut self, ctx: &mut Context) {
+        if let Some(mut output) = self.output.take() {
+            if let Err(err) = output.flush() {
+                ctx.fail(format!("unable to write dead letter: {}", err));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MapDriver, ReduceDriver};
+    use std::env;
+    use std::fs;
+    use std::num::ParseIntError;
+    use std::str;
+
+    #[test]
+    fn test_policy_creation() {
+        let conf = Configuration::default();
+
+        assert_eq!(Policy::new(&conf), Policy::Fail);
+
+        for (value, policy) in [
+            ("fail", Policy::Fail),
+            ("skip", Policy::Skip),
+            ("deadletter", Policy::DeadLetter),
+            ("unknown", Policy::Fail),
+        ] {
+            let mut conf = Configuration::default();
+            conf.insert("efflux.failure.policy", value);
+
+            assert_eq!(Policy::new(&conf), policy);
+        }
+    }
+
+    #[test]
+    fn test_fail_policy() {
+        let input = b"1\ntwo\n3\n";
+        let mut output = Vec::new();
+
+        let result = crate::run_mapper_with(&input[..], &mut output, mapper(TestMapper));
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "task failed: invalid digit found in string"
+        );
+    }
+
+    #[test]
+    fn test_skip_policy() {
+        let mut conf = Configuration::default();
+        conf.insert("efflux.failure.policy", "skip");
+
+        MapDriver::new(mapper(TestMapper))
+            .config(conf)
+            .with_input("1")
+            .with_input("two")
+            .with_input("3")
+            .with_output("1", "2")
+            .with_output("3", "6")
+            .with_counter("efflux", "SKIPPED_RECORDS", 1)
+            .run_test();
+    }
+
+    #[test]
+    fn test_skip_policy_partial_output() {
+        let mut conf = Configuration::default();
+        conf.insert("efflux.failure.policy", "skip");
+
+        let splitter = |_key: usize, value: &[u8], ctx: &mut Context| {
+            for field in str::from_utf8(value).unwrap().split(',') {
+                ctx.write_fmt(field, field.parse::<i32>()?);
+            }
+            Ok::<(), ParseIntError>(())
+        };
+
+        // pairs written before the failure are not retracted
+        MapDriver::new(mapper(splitter))
+            .config(conf)
+            .with_input("1,x,3")
+            .with_input("4")
+            .with_output("1", "1")
+            .with_output("4", "4")
+            .with_counter("efflux", "SKIPPED_RECORDS", 1)
+            .run_test();
+    }
+
+    #[test]
+    fn test_dead_letter_policy() {
+        let path = env::temp_dir().join(format!("efflux-deadletter-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut conf = Configuration::default();
+        conf.insert("efflux.failure.policy", "deadletter");
+        conf.insert("efflux.failure.deadletter.path", path.to_str().unwrap());
+
+        let summer = |key: &[u8], values: &[&[u8]], ctx: &mut Context| {
+            let mut sum = 0;
+            for value in values {
+                sum += str::from_utf8(value).unwrap().parse::<i32>()?;
+            }
+            ctx.write_fmt(str::from_utf8(key).unwrap(), sum);
+            Ok::<(), ParseIntError>(())
+        };
+
+        ReduceDriver::new(reducer(summer))
+            .config(conf)
+            .with_input("a", ["1", "2"])
+            .with_input("b", ["3", "x"])
+            .with_output("a", "3")
+            .with_counter("efflux", "DEAD_LETTER_RECORDS", 2)
+            .run_test();
+
+        let letters = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(letters, b"b\t3\nb\tx\n");
+    }
+
+    #[test]
+    fn test_dead_letter_framing() {
+        let path = env::temp_dir().join(format!("efflux-deadframe-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut conf = Configuration::default();
+        conf.insert("efflux.failure.policy", "deadletter");
+        conf.insert("efflux.failure.deadletter.path", path.to_str().unwrap());
+        conf.insert("stream.map.output.field.separator", ",");
+
+        MapDriver::new(mapper(TestMapper))
+            .config(conf.clone())
+            .with_input("x")
+            .with_counter("efflux", "DEAD_LETTER_RECORDS", 1)
+            .run_test();
+
+        // binary records keep their own framing
+        conf.insert("stream.map.input", "rawbytes");
+
+        MapDriver::new(mapper(TestMapper))
+            .config(conf)
+            .with_input("x\ny")
+            .with_counter("efflux", "DEAD_LETTER_RECORDS", 1)
+            .run_test();
+
+        let letters = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(letters, b"0,x\n\0\0\0\x010\0\0\0\x03x\ny");
+    }
+
+    struct TestMapper;
+
+    impl TryMapper for TestMapper {
+        type Error = ParseIntError;
+
+        fn map(&mut self, _key: usize, value: &[u8], ctx: &mut Context) -> Result<(), Self::Error> {
+            let value = str::from_utf8(value).unwrap();
+            ctx.write_fmt(value, value.parse::<i32>()? * 2);
+            Ok(())
+        }
+    }
+}
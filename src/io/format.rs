@@ -0,0 +1,217 @@
+//! Format bindings to represent the IO protocol of a stage.
+use std::io::{self, Read, Write};
+
+use super::typedbytes;
+use crate::context::{Configuration, Delimiters};
+
+/// Format enum to represent the protocol used to exchange pairs.
+///
+/// Hadoop Streaming sets the format of each side of a stage with the
+/// `-io` flag, which is exposed to a stage as `stream.map.input` (and
+/// so on). Any unknown formats are treated as the default of text.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Format {
+    /// Newline separated records, split into pairs using `Delimiters`.
+    #[default]
+    Text,
+    /// Pairs of values encoded as typed bytes.
+    TypedBytes,
+    /// Pairs of values prefixed by a 4 byte big-endian length.
+    RawBytes,
+}
+
+impl Format {
+    /// Retrieves the input `Format` of a stage from a job `Configuration`.
+    pub fn input(conf: &Configuration) -> Self {
+        Self::from_config(conf, "input")
+    }
+
+    /// Retrieves the output `Format` of a stage from a job `Configuration`.
+    pub fn output(conf: &Configuration) -> Self {
+        Self::from_config(conf, "output")
+    }
+
+    /// Reads the next value of a binary stream in this `Format` into a buffer.
+    ///
+    /// Typed bytes values are self describing, so they're kept intact. As
+    /// text values are not framed, they must be read as lines instead.
+    /// Returns false if the stream ends before the value starts.
+    pub(crate) fn read_value<R>(self, input: &mut R, buf: &mut Vec<u8>) -> io::Result<bool>
+    where
+        R: Read,
+    {
+        match self {
+            Format::Text => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "text values are not framed",
+            )),
+            Format::TypedBytes => typedbytes::read_raw(input, buf),
+            Format::RawBytes => read_raw(input, buf),
+        }
+    }
+
+    /// Writes a key/value pair to an output stream in this `Format`.
+    ///
+    /// Typed bytes keys and values must each contain exactly one encoded
+    /// value, otherwise the pair is rejected as invalid data.
+    pub(crate) fn write<W>(
+        self,
+        output: &mut W,
+        delim: &Delimiters,
+        key: &[u8],
+        val: &[u8],
+    ) -> io::Result<()>
+    where
+        W: Write + ?Sized,
+    {
+        match self {
+            Format::Text => {
+                output.write_all(key)?;
+                output.write_all(delim.output())?;
+                output.write_all(val)?;
+                output.write_all(b"\n")
+            }
+            Format::TypedBytes => {
+                // unframed values would corrupt the rest of the stream
+                typedbytes::validate(key)?;
+                typedbytes::validate(val)?;
+
+                output.write_all(key)?;
+                output.write_all(val)
+            }
+            Format::RawBytes => {
+                output.write_all(&(key.len() as u32).to_be_bytes())?;
+                output.write_all(key)?;
+                output.write_all(&(val.len() as u32).to_be_bytes())?;
+                output.write_all(val)
+            }
+        }
+    }
+
+    /// Parses a `Format` for a side of the current stage.
+    fn from_config(conf: &Configuration, side: &str) -> Self {
+        // check to see if this is map/reduce stage
+        let stage = match conf.get("mapreduce.task.ismap") {
+            Some("true") => "map",
+            _ => "reduce",
+        };
+
+        match conf.get(&format!("stream.{}.{}", stage, side)) {
+            Some("typedbytes") => Format::TypedBytes,
+            Some("rawbytes") => Format::RawBytes,
+            _ => Format::Text,
+        }
+    }
+}
+
+/// Reads the next length prefixed value in a stream into a buffer.
+///
+/// Returns false if the stream ends before the value starts.
+fn read_raw<R>(reader: &mut R, buf: &mut Vec<u8>) -> io::Result<bool>
+where
+    R: Read,
+{
+    let mut len = [0; 4];
+    let mut read = 0;
+
+    // a clean end of stream is not an error
+    while read < len.len() {
+        match reader.read(&mut len[read..])? {
+            0 if read == 0 => return Ok(false),
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => read += n,
+        }
+    }
+
+    let len = u32::from_be_bytes(len) as usize;
+    let start = buf.len();
+
+    reader.take(len as u64).read_to_end(buf)?;
+
+    if buf.len() - start < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_creation() {
+        let env = vec![
+            ("mapreduce.task.ismap", "true"),
+            ("stream.map.input", "typedbytes"),
+            ("stream.map.output", "text"),
+            ("stream.reduce.output", "typedbytes"),
+        ];
+
+        let conf = Configuration::with_env(env.into_iter());
+
+        assert_eq!(Format::input(&conf), Format::TypedBytes);
+        assert_eq!(Format::output(&conf), Format::Text);
+
+        let env = vec![
+            ("mapreduce.task.ismap", "false"),
+            ("stream.reduce.input", "unknown"),
+            ("stream.reduce.output", "typedbytes"),
+        ];
+
+        let conf = Configuration::with_env(env.into_iter());
+
+        assert_eq!(Format::input(&conf), Format::Text);
+        assert_eq!(Format::output(&conf), Format::TypedBytes);
+
+        let env = vec![("stream.reduce.input", "rawbytes")];
+        let conf = Configuration::with_env(env.into_iter());
+
+        assert_eq!(Format::input(&conf), Format::RawBytes);
+    }
+
+    #[test]
+    fn test_format_writing() {
+        let conf = Configuration::default();
+        let delim = Delimiters::new(&conf);
+        let mut output = Vec::new();
+
+        Format::Text
+            .write(&mut output, &delim, b"key", b"value")
+            .unwrap();
+        Format::TypedBytes
+            .write(&mut output, &delim, &[1, 2], &[1, 3])
+            .unwrap();
+        Format::RawBytes
+            .write(&mut output, &delim, b"k\n", b"")
+            .unwrap();
+
+        assert_eq!(
+            output,
+            &b"key\tvalue\n\x01\x02\x01\x03\0\0\0\x02k\n\0\0\0\0"[..]
+        );
+
+        let err = Format::TypedBytes
+            .write(&mut output, &delim, b"1", &[1, 3])
+            .unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_raw_reading() {
+        let mut input = &b"\0\0\0\x03a\tb\0\0\0\0\0\0\0\x05abc"[..];
+        let mut buf = Vec::new();
+
+        assert!(read_raw(&mut input, &mut buf).unwrap());
+        assert_eq!(buf, b"a\tb");
+
+        buf.clear();
+
+        assert!(read_raw(&mut input, &mut buf).unwrap());
+        assert!(buf.is_empty());
+
+        assert!(read_raw(&mut input, &mut buf).is_err());
+        assert!(!read_raw(&mut input, &mut buf).unwrap());
+    }
+}
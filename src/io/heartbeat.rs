@@ -0,0 +1,157 @@
+//! Heartbeat bindings to keep long running tasks alive.
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::context::{Configuration, Contextual};
+
+/// Progress structure to track the number of records read by a stage.
+///
+/// This is shared between the `Input` of a stage and the `Heartbeat`,
+/// so it's stored on the `Context` whilst a `Heartbeat` is running.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Progress(Arc<AtomicUsize>);
+
+impl Progress {
+    /// Records that a record has been read.
+    #[inline]
+    pub(crate) fn incr(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the number of records read.
+    #[inline]
+    pub(crate) fn records(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Contextual for Progress {}
+
+/// Heartbeat structure to report progress from a background thread.
+///
+/// Hadoop kills any task which reports nothing for `mapreduce.task.timeout`
+/// milliseconds, which can happen when a stage does a lot of work for a
+/// single record. When `efflux.heartbeat.enabled` is set, a status update
+/// with the number of records read is written periodically, keeping the
+/// task alive until the `Heartbeat` is dropped.
+///
+/// Updates are written every quarter of the task timeout by default, which
+/// can be changed via `efflux.heartbeat.interval.ms`.
+pub(crate) struct Heartbeat {
+    stopped: Arc<(Mutex<bool>, Condvar)>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Heartbeat {
+    /// Starts a new `Heartbeat` if enabled in the job `Configuration`.
+    pub(crate) fn start(conf: &Configuration, progress: Progress) -> Option<Self> {
+        let interval = interval(conf)?;
+        let stopped = Arc::new((Mutex::new(false), Condvar::new()));
+        let signal = stopped.clone();
+
+        let handle = thread::spawn(move || {
+            let (lock, cvar) = &*signal;
+            let mut stopped = lock.lock().unwrap();
+
+            loop {
+                stopped = cvar.wait_timeout(stopped, interval).unwrap().0;
+
+                if *stopped {
+                    break;
+                }
+
+                update_status!(format!("processed {} records", progress.records()));
+            }
+        });
+
+        Some(Self {
+            stopped,
+            handle: Some(handle),
+        })
+    }
+}
+
+impl Drop for Heartbeat {
+    /// Stops the background thread, waiting for it to exit.
+    fn drop(&mut self) {
+        let (lock, cvar) = &*self.stopped;
+
+        *lock.lock().unwrap() = true;
+        cvar.notify_one();
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Determines the interval of a `Heartbeat`, if one should be started.
+fn interval(conf: &Configuration) -> Option<Duration> {
+    if conf.get_bool("efflux.heartbeat.enabled") != Some(true) {
+        return None;
+    }
+
+    // a timeout of zero disables the timeout
+    let interval = match conf.get_duration("efflux.heartbeat.interval.ms") {
+        Some(interval) => interval,
+        None => {
+            conf.get_duration("mapreduce.task.timeout")
+                .unwrap_or(Duration::from_secs(600))
+                / 4
+        }
+    };
+
+    if interval.is_zero() {
+        return None;
+    }
+
+    Some(interval)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heartbeat_interval() {
+        let mut conf = Configuration::default();
+
+        assert_eq!(interval(&conf), None);
+
+        conf.insert("efflux.heartbeat.enabled", "true");
+
+        assert_eq!(interval(&conf), Some(Duration::from_secs(150)));
+
+        conf.insert("mapreduce.task.timeout", "0");
+
+        assert_eq!(interval(&conf), None);
+
+        conf.insert("efflux.heartbeat.interval.ms", "10");
+
+        assert_eq!(interval(&conf), Some(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn test_heartbeat_lifecycle() {
+        let mut conf = Configuration::default();
+
+        conf.insert("efflux.heartbeat.enabled", "true");
+        conf.insert("efflux.heartbeat.interval.ms", "1");
+
+        let progress = Progress::default();
+        let heartbeat = Heartbeat::start(&conf, progress.clone());
+
+        assert!(heartbeat.is_some());
+
+        progress.incr();
+        thread::sleep(Duration::from_millis(5));
+
+        // dropping waits for the thread to exit
+        drop(heartbeat);
+
+        assert_eq!(progress.records(), 1);
+        assert_eq!(Arc::strong_count(&progress.0), 1);
+    }
+}
@@ -0,0 +1,831 @@
+//! IO binding module for the `efflux` crate.
+//!
+//! Provides lifecycles for Hadoop Streaming IO, to allow the rest
+//! of this crate to be a little more ignorant of how inputs flow.
+//!
+//! Input and output default to newline separated text, but can be
+//! switched to binary protocols (typed bytes or raw bytes) by setting the
+//! `Format` of a stage in the job `Configuration`.
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process;
+
+use crate::context::{Configuration, Context, Delimiters};
+use crate::error::Error;
+
+mod format;
+mod heartbeat;
+mod recovery;
+pub mod typedbytes;
+mod xml;
+
+pub use self::format::Format;
+pub use self::recovery::Recovery;
+
+use self::heartbeat::{Heartbeat, Progress};
+use self::recovery::{Action, Errors};
+use self::xml::Markers;
+
+/// Lifecycle trait to allow hooking into IO streams.
+///
+/// This will be implemented by all stages of MapReduce (e.g. to
+/// appropriately handle buffering for the reduction stage). All
+/// trait methods default to noop, as they're all optional.
+pub trait Lifecycle {
+    /// Startup hook for the IO stream.
+    fn on_start(&mut self, _ctx: &mut Context) {}
+
+    /// Entry hook for the IO stream to handle input values.
+    fn on_entry(&mut self, _input: &[u8], _ctx: &mut Context) {}
+
+    /// Input hook for the IO stream to handle all input values.
+    ///
+    /// The default implementation passes each entry through to the entry
+    /// hook, but this can be overridden when a stage needs to pull entries
+    /// from the stream directly (rather than having them pushed). Input
+    /// should no longer be consumed once the `Context` has failed.
+    fn on_input(&mut self, input: &mut Input, ctx: &mut Context) {
+        while !ctx.failed() {
+            match input.next() {
+                Some(entry) => self.on_entry(entry, ctx),
+                None => break,
+            }
+        }
+    }
+
+    /// Finalization hook for the IO stream.
+    fn on_end(&mut self, _ctx: &mut Context) {}
+}
+
+/// Input structure to represent the entries of an IO stream.
+///
+/// Entries are read lazily from the stream, and each entry is only valid
+/// until the next entry is read. This avoids allocating for every entry.
+///
+/// The input `Format` of the stage determines how entries are read; text
+/// entries are lines which are split into pairs using the `Delimiters`,
+/// whereas binary formats provide keys and values separately.
+///
+/// Text entries are separated by newlines by default, but mapping stages
+/// can use any (multi-byte) delimiter by setting the Hadoop option of
+/// `textinputformat.record.delimiter`, such as `\n\n` for records split
+/// by blank lines. Mapping stages can also read fragments of XML input as
+/// entries, via `stream.recordreader.begin` and `stream.recordreader.end`.
+///
+/// A read error ends the stream, with the error being reported once the
+/// stage has consumed the input. Errors reading text input can instead be
+/// retried or skipped, based on the `Recovery` set in the `Configuration`.
+pub struct Input<'a> {
+    reader: Reader<'a>,
+    errors: Errors,
+    progress: Option<Progress>,
+    position: usize,
+}
+
+/// Reader enum to represent the input protocols of a stream.
+enum Reader<'a> {
+    Text {
+        input: Box<dyn BufRead + 'a>,
+        line: Vec<u8>,
+        delim: Delimiters,
+        terminator: Option<Vec<u8>>,
+    },
+    Xml {
+        input: Box<dyn BufRead + 'a>,
+        record: Vec<u8>,
+        markers: Markers,
+    },
+    Binary {
+        format: Format,
+        input: Box<dyn BufRead + 'a>,
+        keyed: bool,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    },
+}
+
+impl<'a> Input<'a> {
+    /// Constructs a new `Input` from a readable stream.
+    ///
+    /// The input `Format` and `Delimiters` are taken from the `Context`.
+    pub(crate) fn new<I>(input: I, ctx: &Context) -> Self
+    where
+        I: Read + 'a,
+    {
+        let input: Box<dyn BufRead + 'a> = Box::new(BufReader::new(input));
+
+        // contexts without a configuration use the defaults
+        let default = Configuration::default();
+        let conf = ctx.get::<Configuration>().unwrap_or(&default);
+
+        let reader = match (Format::input(conf), Markers::new(conf)) {
+            (Format::Text, Some(markers)) => Reader::Xml {
+                input,
+                record: Vec::new(),
+                markers,
+            },
+            (Format::Text, None) => Reader::Text {
+                input,
+                line: Vec::new(),
+                delim: ctx
+                    .get::<Delimiters>()
+                    .cloned()
+                    .unwrap_or_else(|| Delimiters::new(conf)),
+                terminator: terminator(conf),
+            },
+            (format, _) => Reader::Binary {
+                format,
+                input,
+                keyed: keyed(conf),
+                key: Vec::new(),
+                value: Vec::new(),
+            },
+        };
+
+        Self {
+            reader,
+            errors: Errors::new(conf),
+            progress: ctx.get::<Progress>().cloned(),
+            position: 0,
+        }
+    }
+
+    /// Retrieves the next entry from the stream, if any.
+    ///
+    /// For text input this is an entire line, otherwise this is the value
+    /// of the next pair (as mapping stages are only provided values).
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<&[u8]> {
+        self.next_with_offset().map(|(_, entry)| entry)
+    }
+
+    /// Retrieves the next entry from the stream, with its byte offset.
+    ///
+    /// The offset is relative to the start of the stream. For text input
+    /// this accounts for the real line terminators (which may be `\n`, `\r`
+    /// or `\r\n`) and any bytes skipped due to read errors, or between XML
+    /// fragments. For binary input only the lengths of values are counted.
+    pub fn next_with_offset(&mut self) -> Option<(usize, &[u8])> {
+        let position = &mut self.position;
+        let entry = match &mut self.reader {
+            Reader::Text {
+                input,
+                line,
+                terminator,
+                ..
+            } => read_line(
+                input,
+                line,
+                terminator.as_deref(),
+                position,
+                &mut self.errors,
+            ),
+            Reader::Xml {
+                input,
+                record,
+                markers,
+            } => xml::read_fragment(input, record, markers, position, &mut self.errors),
+            Reader::Binary {
+                format,
+                input,
+                keyed,
+                key,
+                value,
+            } => {
+                read_pair(*format, input, *keyed, key, value, &mut self.errors).map(|(_, value)| {
+                    *position += value.len();
+                    (*position - value.len(), value)
+                })
+            }
+        };
+
+        if let (Some(_), Some(progress)) = (&entry, &self.progress) {
+            progress.incr();
+        }
+
+        entry
+    }
+
+    /// Retrieves the next key/value pair from the stream, if any.
+    ///
+    /// For text input each line is split into a pair using the input
+    /// `Delimiters`, and XML fragments are provided as keys with an empty
+    /// value (as in Hadoop). For binary input an empty key is provided when
+    /// the stream only contains values.
+    pub fn next_pair(&mut self) -> Option<(&[u8], &[u8])> {
+        let position = &mut self.position;
+        let pair = match &mut self.reader {
+            Reader::Text {
+                input,
+                line,
+                delim,
+                terminator,
+            } => read_line(
+                input,
+                line,
+                terminator.as_deref(),
+                position,
+                &mut self.errors,
+            )
+            .map(|(_, line)| delim.split_input(line)),
+            Reader::Xml {
+                input,
+                record,
+                markers,
+            } => xml::read_fragment(input, record, markers, position, &mut self.errors)
+                .map(|(_, fragment)| (fragment, &b""[..])),
+            Reader::Binary {
+                format,
+                input,
+                keyed,
+                key,
+                value,
+            } => read_pair(*format, input, *keyed, key, value, &mut self.errors),
+        };
+
+        if let (Some(_), Some(progress)) = (&pair, &self.progress) {
+            progress.incr();
+        }
+
+        pair
+    }
+
+    /// Retrieves the number of bytes consumed from the stream so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
+/// Executes an IO `Lifecycle` against `io::stdin`.
+///
+/// All output written via the `Context` will be sent to `io::stdout`. If
+/// the stage fails, the reason is written to the task logs and the process
+/// exits with a non-zero status, which causes Hadoop to fail the task.
+///
+/// If the process reading the output exits early, the stage is stopped
+/// quietly rather than failing, in the same way as most UNIX utilities.
+pub fn run_lifecycle<L>(lifecycle: L)
+where
+    L: Lifecycle,
+{
+    // lock stdin for perf
+    let stdin = io::stdin();
+    let stdin_lock = stdin.lock();
+
+    // execute against the standard streams
+    match run_lifecycle_with(stdin_lock, io::stdout(), lifecycle) {
+        Err(err) if err.is_broken_pipe() => process::exit(0),
+        Err(err) => {
+            log!("efflux: {}", err);
+            process::exit(1);
+        }
+        Ok(()) => (),
+    }
+}
+
+/// Executes an IO `Lifecycle` against a custom input and output.
+///
+/// This allows a `Lifecycle` to be driven by something other than the
+/// standard streams, such as files or in-memory buffers. Stages which
+/// fail (via `Context::fail`, a failed write, or a failed read) stop
+/// immediately and return an `Error`, without firing the finalization
+/// hooks. Any buffered output and counters are still flushed.
+///
+/// When `efflux.heartbeat.enabled` is set, progress is reported from a
+/// background thread until the finalization hooks have completed, which
+/// stops Hadoop from killing tasks which take a long time per record.
+pub fn run_lifecycle_with<L, I, O>(input: I, output: O, mut lifecycle: L) -> Result<(), Error>
+where
+    L: Lifecycle,
+    I: Read,
+    O: Write,
+{
+    // create a job context
+    let mut ctx = Context::with_output(output);
+
+    run_context(input, &mut lifecycle, &mut ctx)
+}
+
+/// Executes an IO `Lifecycle` against a custom input and `Context`.
+fn run_context<L, I>(input: I, lifecycle: &mut L, ctx: &mut Context) -> Result<(), Error>
+where
+    L: Lifecycle,
+    I: Read,
+{
+    // keep long running tasks alive, if enabled
+    let progress = Progress::default();
+    let heartbeat = ctx
+        .get::<Configuration>()
+        .and_then(|conf| Heartbeat::start(conf, progress.clone()));
+
+    if heartbeat.is_some() {
+        ctx.insert(progress);
+    }
+
+    // fire the startup hooks
+    lifecycle.on_start(ctx);
+
+    // fire the entry hooks for all inputs, holding any read error
+    let mut read = Ok(());
+
+    if !ctx.failed() {
+        read = run_entries(input, lifecycle, ctx);
+    }
+
+    // fire the finalization hooks
+    if !ctx.failed() && read.is_ok() {
+        lifecycle.on_end(ctx);
+    }
+
+    // the task is finished, so stop reporting progress
+    drop(heartbeat);
+
+    // flush any trailing output and counters, even after a read error
+    ctx.flush();
+
+    read.map_err(Error::Read)?;
+
+    match ctx.take_failure() {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+/// Feeds all entries of an input through the entry hooks of a `Lifecycle`.
+///
+/// This does not fire the startup or finalization hooks, which allows
+/// a single `Lifecycle` to be driven by several inputs in sequence. Any
+/// error which ended the input early is returned once it's consumed.
+pub(crate) fn run_entries<L, I>(input: I, lifecycle: &mut L, ctx: &mut Context) -> io::Result<()>
+where
+    L: Lifecycle + ?Sized,
+    I: Read,
+{
+    let mut input = Input::new(input, ctx);
+    lifecycle.on_input(&mut input, ctx);
+
+    // errors which were skipped are counted
+    let skipped = input.errors.skipped();
+    if skipped > 0 {
+        ctx.counter("efflux", "SKIPPED_INPUT_ERRORS")
+            .incr(skipped as i64);
+    }
+
+    match input.errors.take() {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+/// Determines whether binary input contains keys as well as values.
+///
+/// Reduction stages are always provided keys. Mapping stages are only
+/// provided keys when `stream.map.input.ignoreKey` is false, which is
+/// the Hadoop default for any input format other than `TextInputFormat`.
+fn keyed(conf: &Configuration) -> bool {
+    conf.get("mapreduce.task.ismap") != Some("true") || map_keyed(conf)
+}
+
+/// Determines whether the input of a mapping stage contains keys.
+pub(crate) fn map_keyed(conf: &Configuration) -> bool {
+    if let Some(ignore) = conf.get("stream.map.input.ignoreKey") {
+        return ignore == "false";
+    }
+
+    conf.get("mapreduce.job.inputformat.class")
+        .or_else(|| conf.get("mapred.input.format.class"))
+        .map(|class| !class.ends_with(".TextInputFormat"))
+        .unwrap_or(false)
+}
+
+/// Determines the custom record terminator of text input, if any.
+///
+/// As in Hadoop, this only applies to the input of mapping stages; the
+/// input of reduction stages is always separated by newlines.
+fn terminator(conf: &Configuration) -> Option<Vec<u8>> {
+    if conf.get("mapreduce.task.ismap") != Some("true") {
+        return None;
+    }
+
+    conf.get("textinputformat.record.delimiter")
+        .filter(|delim| !delim.is_empty())
+        .map(|delim| delim.as_bytes().to_vec())
+}
+
+/// Reads the next line from a stream into a buffer, without the newline.
+///
+/// Lines end with `\n`, `\r\n` or a lone `\r` (as in Hadoop's `LineReader`)
+/// unless a custom terminator is provided, in which case lines end with the
+/// terminator exactly. As bytes which are read before an error remain in
+/// the buffer, a read can be retried without losing any of the line. The
+/// offset of the line within the stream is returned alongside the line,
+/// based on the position provided (which is moved past all bytes consumed).
+fn read_line<'b, R>(
+    input: &mut R,
+    line: &'b mut Vec<u8>,
+    terminator: Option<&[u8]>,
+    position: &mut usize,
+    errors: &mut Errors,
+) -> Option<(usize, &'b [u8])>
+where
+    R: BufRead + ?Sized,
+{
+    line.clear();
+
+    let delim = terminator.unwrap_or(b"\n");
+    let last = delim[delim.len() - 1];
+
+    let mut attempt = 0;
+    let mut discard = false;
+    let mut discarded = 0;
+
+    loop {
+        let result = match terminator {
+            Some(_) => input.read_until(last, line),
+            None => read_until_eol(input, line),
+        };
+
+        match result {
+            Ok(read) => {
+                let complete = match terminator {
+                    Some(_) => line.ends_with(delim),
+                    None => line.ends_with(b"\n") || line.ends_with(b"\r"),
+                };
+
+                // multi-byte terminators may need several reads
+                if !complete && read > 0 {
+                    continue;
+                }
+
+                // the remainder of a skipped line is discarded
+                if discard {
+                    discarded += line.len();
+                    line.clear();
+                    discard = false;
+
+                    if complete {
+                        continue;
+                    }
+                }
+
+                break;
+            }
+            Err(err) => {
+                attempt += 1;
+
+                match errors.handle(err, attempt, true) {
+                    Action::Retry => (),
+                    Action::Skip => {
+                        discard = !line.is_empty();
+                        discarded += line.len();
+                        line.clear();
+                    }
+                    Action::Stop => return None,
+                }
+            }
+        }
+    }
+
+    if line.is_empty() {
+        return None;
+    }
+
+    let offset = *position + discarded;
+    let mut len = line.len();
+
+    *position = offset + len;
+
+    // trim a custom terminator exactly
+    if terminator.is_some() {
+        if line.ends_with(delim) {
+            len -= delim.len();
+        }
+        return Some((offset, &line[..len]));
+    }
+
+    // trim the newline, including a carriage return
+    if line[len - 1] == b'\n' {
+        len -= 1;
+    }
+
+    if len > 0 && line[len - 1] == b'\r' {
+        len -= 1;
+    }
+
+    Some((offset, &line[..len]))
+}
+
+/// Reads bytes from a stream into a buffer until the end of a line.
+///
+/// This mirrors `BufRead::read_until`, but a line ends at either `\n` or
+/// `\r`, including any `\n` directly after a `\r`. A buffer ending with
+/// `\r` is completed by peeking at the next byte, which allows a read to
+/// be retried after an error between the two.
+fn read_until_eol<R>(input: &mut R, line: &mut Vec<u8>) -> io::Result<usize>
+where
+    R: BufRead + ?Sized,
+{
+    let mut read = 0;
+
+    loop {
+        let (done, used) = {
+            let buf = match input.fill_buf() {
+                Ok(buf) => buf,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+
+            if line.last() == Some(&b'\r') {
+                // a carriage return only takes a directly following newline
+                if buf.first() == Some(&b'\n') {
+                    line.push(b'\n');
+                    (true, 1)
+                } else {
+                    (true, 0)
+                }
+            } else if let Some(idx) = buf.iter().position(|b| *b == b'\n' || *b == b'\r') {
+                line.extend_from_slice(&buf[..=idx]);
+                (buf[idx] == b'\n', idx + 1)
+            } else {
+                line.extend_from_slice(buf);
+                (buf.is_empty(), buf.len())
+            }
+        };
+
+        input.consume(used);
+        read += used;
+
+        if done {
+            return Ok(read);
+        }
+    }
+}
+
+/// Reads the next binary key/value pair from a stream into buffers.
+///
+/// Binary framing can't be recovered after a read error, so any error
+/// always ends the stream.
+fn read_pair<'b, R>(
+    format: Format,
+    input: &mut R,
+    keyed: bool,
+    key: &'b mut Vec<u8>,
+    value: &'b mut Vec<u8>,
+    errors: &mut Errors,
+) -> Option<(&'b [u8], &'b [u8])>
+where
+    R: Read,
+{
+    key.clear();
+    value.clear();
+
+    let mut read = |buf: &mut Vec<u8>| match format.read_value(input, buf) {
+        Ok(read) => read,
+        Err(err) => {
+            errors.handle(err, 1, false);
+            false
+        }
+    };
+
+    if keyed && !read(key) {
+        return None;
+    }
+
+    if !read(value) {
+        return None;
+    }
+
+    Some((key, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::Counters;
+    use std::collections::VecDeque;
+
+    #[test]
+    fn test_input_read_error() {
+        let ctx = Context::with_config(Configuration::default(), io::sink());
+        let mut input = Input::new(TestReader::new(), &ctx);
+
+        assert_eq!(input.next(), Some(&b"one"[..]));
+        assert_eq!(input.next(), None);
+        assert_eq!(input.errors.take().unwrap().to_string(), "broken");
+    }
+
+    #[test]
+    fn test_input_read_retry() {
+        let mut conf = Configuration::default();
+        conf.insert("efflux.input.error.policy", "retry");
+
+        let ctx = Context::with_config(conf, io::sink());
+        let mut input = Input::new(TestReader::new(), &ctx);
+
+        assert_eq!(input.next(), Some(&b"one"[..]));
+        assert_eq!(input.next(), Some(&b"two"[..]));
+        assert_eq!(input.next(), Some(&b"three"[..]));
+        assert_eq!(input.next(), None);
+        assert!(input.errors.take().is_none());
+    }
+
+    #[test]
+    fn test_input_read_skip() {
+        let mut conf = Configuration::default();
+        conf.insert("efflux.input.error.policy", "skip");
+
+        let ctx = Context::with_config(conf, io::sink());
+        let mut input = Input::new(TestReader::new(), &ctx);
+
+        assert_eq!(input.next(), Some(&b"one"[..]));
+        assert_eq!(input.next(), Some(&b"three"[..]));
+        assert_eq!(input.next(), None);
+        assert_eq!(input.errors.skipped(), 1);
+        assert!(input.errors.take().is_none());
+    }
+
+    #[test]
+    fn test_input_carriage_returns() {
+        let ctx = Context::with_config(Configuration::default(), io::sink());
+        let mut input = Input::new(&b"one\rtwo\r\nthree\r\rfour\nfive\r"[..], &ctx);
+
+        assert_eq!(input.next_with_offset(), Some((0, &b"one"[..])));
+        assert_eq!(input.next_with_offset(), Some((4, &b"two"[..])));
+        assert_eq!(input.next_with_offset(), Some((9, &b"three"[..])));
+        assert_eq!(input.next_with_offset(), Some((15, &b""[..])));
+        assert_eq!(input.next_with_offset(), Some((16, &b"four"[..])));
+        assert_eq!(input.next_with_offset(), Some((21, &b"five"[..])));
+        assert_eq!(input.next_with_offset(), None);
+        assert_eq!(input.position(), 26);
+
+        // terminators split across reads are still joined
+        let mut reader = io::BufReader::with_capacity(1, &b"a\r\nb\rc"[..]);
+        let mut errors = Errors::new(&Configuration::default());
+        let mut line = Vec::new();
+        let mut position = 0;
+
+        for expected in [(0, &b"a"[..]), (3, &b"b"[..]), (5, &b"c"[..])] {
+            let found = read_line(&mut reader, &mut line, None, &mut position, &mut errors);
+            assert_eq!(found, Some(expected));
+        }
+
+        assert_eq!(position, 6);
+    }
+
+    #[test]
+    fn test_input_record_delimiter() {
+        let mut conf = Configuration::default();
+        conf.insert("mapreduce.task.ismap", "true");
+        conf.insert("textinputformat.record.delimiter", "\n\n");
+
+        let ctx = Context::with_config(conf, io::sink());
+        let mut input = Input::new(&b"one\ntwo\n\nthree\n\n\n\nfour\n"[..], &ctx);
+
+        assert_eq!(input.next_with_offset(), Some((0, &b"one\ntwo"[..])));
+        assert_eq!(input.next_with_offset(), Some((9, &b"three"[..])));
+        assert_eq!(input.next_with_offset(), Some((16, &b""[..])));
+        assert_eq!(input.next_with_offset(), Some((18, &b"four\n"[..])));
+        assert_eq!(input.next_with_offset(), None);
+        assert_eq!(input.position(), 23);
+    }
+
+    #[test]
+    fn test_input_record_delimiter_reduce() {
+        let mut conf = Configuration::default();
+        conf.insert("mapreduce.task.ismap", "false");
+        conf.insert("textinputformat.record.delimiter", "\u{1e}");
+
+        let ctx = Context::with_config(conf, io::sink());
+        let mut input = Input::new(&b"one\x1etwo\n"[..], &ctx);
+
+        assert_eq!(input.next(), Some(&b"one\x1etwo"[..]));
+        assert_eq!(input.next(), None);
+    }
+
+    #[test]
+    fn test_input_xml_fragments() {
+        let mut conf = Configuration::default();
+        conf.insert("mapreduce.task.ismap", "true");
+        conf.insert("stream.recordreader.begin", "<page>");
+        conf.insert("stream.recordreader.end", "</page>");
+
+        let ctx = Context::with_config(conf, io::sink());
+        let mut input = Input::new(
+            &b"<pages>\n<page>\none\n</page>\n<page>two</page>"[..],
+            &ctx,
+        );
+
+        assert_eq!(
+            input.next_pair(),
+            Some((&b"<page>\none\n</page>"[..], &b""[..]))
+        );
+        assert_eq!(
+            input.next_with_offset(),
+            Some((27, &b"<page>two</page>"[..]))
+        );
+        assert_eq!(input.next(), None);
+    }
+
+    #[test]
+    fn test_input_progress() {
+        let mut ctx = Context::with_config(Configuration::default(), io::sink());
+        let progress = Progress::default();
+
+        ctx.insert(progress.clone());
+
+        let mut input = Input::new(&b"one\ntwo\n"[..], &ctx);
+
+        while input.next_pair().is_some() {}
+
+        assert_eq!(progress.records(), 2);
+    }
+
+    #[test]
+    fn test_run_write_error() {
+        let input = &b"one\ntwo\n"[..];
+        let result = run_lifecycle_with(input, TestWriter, TestLifecycle);
+
+        assert!(result.unwrap_err().is_broken_pipe());
+    }
+
+    #[test]
+    fn test_run_read_error() {
+        let result = run_lifecycle_with(TestReader::new(), io::sink(), TestLifecycle);
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "unable to read input: broken"
+        );
+    }
+
+    #[test]
+    fn test_run_read_error_flush() {
+        let mut output = Vec::new();
+
+        {
+            let mut ctx = Context::with_output(&mut output);
+            let result = run_context(TestReader::new(), &mut TestLifecycle, &mut ctx);
+
+            assert_eq!(
+                result.unwrap_err().to_string(),
+                "unable to read input: broken"
+            );
+
+            let counters = ctx.get::<Counters>().unwrap();
+
+            assert_eq!(counters.get("efflux", "ENTRIES"), 1);
+            assert!(!counters.pending());
+        }
+
+        // output written before the error is not lost
+        assert_eq!(output, b"one\t\n");
+    }
+
+    /// Lifecycle which writes every entry as a key.
+    struct TestLifecycle;
+
+    impl Lifecycle for TestLifecycle {
+        fn on_entry(&mut self, input: &[u8], ctx: &mut Context) {
+            ctx.counter("efflux", "ENTRIES").incr(1);
+            ctx.write(input, b"");
+        }
+    }
+
+    /// Writer which has been closed by the reading process.
+    struct TestWriter;
+
+    impl Write for TestWriter {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Reader which fails part way through the second line.
+    struct TestReader(VecDeque<io::Result<&'static [u8]>>);
+
+    impl TestReader {
+        fn new() -> Self {
+            TestReader(VecDeque::from(vec![
+                Ok(&b"one\ntw"[..]),
+                Err(io::Error::other("broken")),
+                Ok(&b"o\nthree\n"[..]),
+            ]))
+        }
+    }
+
+    impl Read for TestReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.pop_front() {
+                None => Ok(0),
+                Some(Err(err)) => Err(err),
+                Some(Ok(bytes)) => {
+                    buf[..bytes.len()].copy_from_slice(bytes);
+                    Ok(bytes.len())
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! Recovery bindings to handle errors when reading stage input.
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+use crate::context::Configuration;
+
+/// Recovery enum to represent how read errors of an input are handled.
+///
+/// Read errors fail the task by default, as continuing would result in
+/// a task which succeeds with only part of the output. This can be set
+/// via `efflux.input.error.policy` when errors are known to be transient.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Recovery {
+    /// Fail the task on the first read error.
+    #[default]
+    Fail,
+    /// Retry the read which failed, keeping any bytes already read.
+    Retry,
+    /// Skip the record which failed to read, counting the error.
+    Skip,
+}
+
+impl Recovery {
+    /// Creates a new `Recovery` from a job `Configuration`.
+    pub fn new(conf: &Configuration) -> Self {
+        match conf.get("efflux.input.error.policy") {
+            Some("retry") => Recovery::Retry,
+            Some("skip") => Recovery::Skip,
+            _ => Recovery::Fail,
+        }
+    }
+}
+
+/// Action enum to represent what to do after a read error.
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) enum Action {
+    Retry,
+    Skip,
+    Stop,
+}
+
+/// Errors structure to track the read errors of an input.
+///
+/// Recovery is attempted up to `efflux.input.error.retries` times for
+/// each record (defaulting to 3), after which the input is stopped. The
+/// error which stopped the input is kept so it can be reported.
+#[derive(Debug, Default)]
+pub(crate) struct Errors {
+    recovery: Recovery,
+    retries: usize,
+    skipped: usize,
+    error: Option<io::Error>,
+}
+
+impl Errors {
+    /// Creates a new `Errors` from a job `Configuration`.
+    pub(crate) fn new(conf: &Configuration) -> Self {
+        Self {
+            recovery: Recovery::new(conf),
+            retries: conf.get_parsed("efflux.input.error.retries").unwrap_or(3),
+            skipped: 0,
+            error: None,
+        }
+    }
+
+    /// Handles a read error, given the number of errors for the record.
+    ///
+    /// Inputs which can't resynchronize after an error (such as binary
+    /// framing) should not be recovered, and so are always stopped.
+    pub(crate) fn handle(&mut self, err: io::Error, attempt: usize, recover: bool) -> Action {
+        if !recover || self.recovery == Recovery::Fail || attempt > self.retries {
+            self.error = Some(err);
+            return Action::Stop;
+        }
+
+        match self.recovery {
+            Recovery::Retry => {
+                log!("efflux: retrying input after error: {}", err);
+                thread::sleep(Duration::from_millis(100 * attempt as u64));
+                Action::Retry
+            }
+            _ => {
+                log!("efflux: skipping input after error: {}", err);
+                self.skipped += 1;
+                Action::Skip
+            }
+        }
+    }
+
+    /// Returns the number of errors which were skipped.
+    #[inline]
+    pub(crate) fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// Takes the error which stopped the input, if any.
+    #[inline]
+    pub(crate) fn take(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recovery_creation() {
+        for (value, recovery) in [
+            ("fail", Recovery::Fail),
+            ("retry", Recovery::Retry),
+            ("skip", Recovery::Skip),
+            ("unknown", Recovery::Fail),
+        ] {
+            let mut conf = Configuration::default();
+            conf.insert("efflux.input.error.policy", value);
+
+            assert_eq!(Recovery::new(&conf), recovery);
+        }
+    }
+
+    #[test]
+    fn test_error_handling() {
+        let mut conf = Configuration::default();
+        conf.insert("efflux.input.error.policy", "skip");
+        conf.insert("efflux.input.error.retries", "1");
+
+        let mut errors = Errors::new(&conf);
+        let error = || io::Error::other("broken");
+
+        assert_eq!(errors.handle(error(), 1, true), Action::Skip);
+        assert_eq!(errors.skipped(), 1);
+        assert!(errors.take().is_none());
+
+        assert_eq!(errors.handle(error(), 2, true), Action::Stop);
+        assert_eq!(errors.take().unwrap().to_string(), "broken");
+
+        assert_eq!(errors.handle(error(), 1, false), Action::Stop);
+        assert!(errors.take().is_some());
+    }
+}
@@ -0,0 +1,513 @@
+//! Typed bytes bindings, as used by Hadoop Streaming's `-io typedbytes`.
+//!
+//! Typed bytes is a binary format in which every value is prefixed by a
+//! type code, allowing keys and values to contain any bytes (including
+//! the delimiters used by the text protocol). When a job is configured to
+//! use typed bytes, the entries provided to a stage are the raw encoded
+//! values, which can be decoded into a `Value`:
+//!
+//! ```rust
+//! # extern crate efflux;
+//! use efflux::io::typedbytes::Value;
+//!
+//! // encode a value into typed bytes
+//! let bytes = Value::from("hello").encode();
+//!
+//! // decode the same value back out
+//! let value = Value::decode(&bytes).expect("invalid typed bytes");
+//!
+//! assert_eq!(value, Value::String("hello".to_string()));
+//! ```
+use std::io::{self, Read, Write};
+
+// type codes defined by the Hadoop `Type` enum
+const BYTES: u8 = 0;
+const BYTE: u8 = 1;
+const BOOL: u8 = 2;
+const INT: u8 = 3;
+const LONG: u8 = 4;
+const FLOAT: u8 = 5;
+const DOUBLE: u8 = 6;
+const STRING: u8 = 7;
+const VECTOR: u8 = 8;
+const LIST: u8 = 9;
+const MAP: u8 = 10;
+const WRITABLE: u8 = 50;
+const MARKER: u8 = 255;
+
+// maximum nesting of containers, to avoid exhausting the stack
+const MAX_DEPTH: usize = 128;
+
+/// Value enum to represent a single typed bytes value.
+///
+/// Maps are stored as a list of pairs, as typed bytes places no
+/// restrictions on the types used as keys, and keeps their order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// A sequence of raw bytes (type code `0`).
+    Bytes(Vec<u8>),
+    /// A single signed byte (type code `1`).
+    Byte(i8),
+    /// A boolean (type code `2`).
+    Bool(bool),
+    /// A 32 bit signed integer (type code `3`).
+    Int(i32),
+    /// A 64 bit signed integer (type code `4`).
+    Long(i64),
+    /// A 32 bit floating point number (type code `5`).
+    Float(f32),
+    /// A 64 bit floating point number (type code `6`).
+    Double(f64),
+    /// A UTF-8 string (type code `7`).
+    String(String),
+    /// A fixed length sequence of values (type code `8`).
+    Vector(Vec<Value>),
+    /// A marker terminated sequence of values (type code `9`).
+    List(Vec<Value>),
+    /// A sequence of key/value pairs (type code `10`).
+    Map(Vec<(Value, Value)>),
+}
+
+impl Value {
+    /// Decodes a `Value` from a slice of typed bytes.
+    ///
+    /// The slice must contain exactly one value, such as an entry
+    /// provided to a stage when using typed bytes as input.
+    pub fn decode(mut bytes: &[u8]) -> io::Result<Value> {
+        let value = Self::read(&mut bytes)?;
+
+        if !bytes.is_empty() {
+            return Err(invalid("trailing bytes after value"));
+        }
+
+        Ok(value)
+    }
+
+    /// Reads a `Value` from a stream of typed bytes.
+    ///
+    /// Containers may be nested up to 128 levels deep; anything deeper
+    /// is treated as invalid data, rather than exhausting the stack.
+    pub fn read<R>(reader: &mut R) -> io::Result<Value>
+    where
+        R: Read,
+    {
+        Self::read_depth(reader, 0)
+    }
+
+    /// Encodes a `Value` into a vector of typed bytes.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.write(&mut bytes)
+            .expect("vector writes are infallible");
+        bytes
+    }
+
+    /// Writes a `Value` to a stream as typed bytes.
+    pub fn write<W>(&self, writer: &mut W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        match self {
+            Value::Bytes(bytes) => {
+                writer.write_all(&[BYTES])?;
+                write_len(writer, bytes.len())?;
+                writer.write_all(bytes)
+            }
+            Value::Byte(byte) => writer.write_all(&[BYTE, *byte as u8]),
+            Value::Bool(bool) => writer.write_all(&[BOOL, *bool as u8]),
+            Value::Int(int) => {
+                writer.write_all(&[INT])?;
+                writer.write_all(&int.to_be_bytes())
+            }
+            Value::Long(long) => {
+                writer.write_all(&[LONG])?;
+                writer.write_all(&long.to_be_bytes())
+            }
+            Value::Float(float) => {
+                writer.write_all(&[FLOAT])?;
+                writer.write_all(&float.to_bits().to_be_bytes())
+            }
+            Value::Double(double) => {
+                writer.write_all(&[DOUBLE])?;
+                writer.write_all(&double.to_bits().to_be_bytes())
+            }
+            Value::String(string) => {
+                writer.write_all(&[STRING])?;
+                write_len(writer, string.len())?;
+                writer.write_all(string.as_bytes())
+            }
+            Value::Vector(values) => {
+                writer.write_all(&[VECTOR])?;
+                write_len(writer, values.len())?;
+                for value in values {
+                    value.write(writer)?;
+                }
+                Ok(())
+            }
+            Value::List(values) => {
+                writer.write_all(&[LIST])?;
+                for value in values {
+                    value.write(writer)?;
+                }
+                writer.write_all(&[MARKER])
+            }
+            Value::Map(pairs) => {
+                writer.write_all(&[MAP])?;
+                write_len(writer, pairs.len())?;
+                for (key, value) in pairs {
+                    key.write(writer)?;
+                    value.write(writer)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Reads a `Value` from a stream, at the provided depth of nesting.
+    fn read_depth<R>(reader: &mut R, depth: usize) -> io::Result<Value>
+    where
+        R: Read,
+    {
+        let code = read_array::<_, 1>(reader)?[0];
+        Self::read_code(reader, code, depth)
+    }
+
+    /// Reads the body of a `Value` with the provided type code.
+    fn read_code<R>(reader: &mut R, code: u8, depth: usize) -> io::Result<Value>
+    where
+        R: Read,
+    {
+        if depth >= MAX_DEPTH && matches!(code, VECTOR | LIST | MAP) {
+            return Err(invalid("values nested too deeply"));
+        }
+
+        Ok(match code {
+            BYTES => Value::Bytes(read_vec(reader)?),
+            BYTE => Value::Byte(read_array::<_, 1>(reader)?[0] as i8),
+            BOOL => Value::Bool(read_array::<_, 1>(reader)?[0] != 0),
+            INT => Value::Int(i32::from_be_bytes(read_array(reader)?)),
+            LONG => Value::Long(i64::from_be_bytes(read_array(reader)?)),
+            FLOAT => Value::Float(f32::from_bits(u32::from_be_bytes(read_array(reader)?))),
+            DOUBLE => Value::Double(f64::from_bits(u64::from_be_bytes(read_array(reader)?))),
+            STRING => Value::String(
+                String::from_utf8(read_vec(reader)?)
+                    .map_err(|_| invalid("invalid utf-8 string"))?,
+            ),
+            VECTOR => {
+                let len = read_len(reader)?;
+                let mut values = Vec::new();
+                for _ in 0..len {
+                    values.push(Self::read_depth(reader, depth + 1)?);
+                }
+                Value::Vector(values)
+            }
+            LIST => {
+                let mut values = Vec::new();
+                loop {
+                    match read_array::<_, 1>(reader)?[0] {
+                        MARKER => break,
+                        code => values.push(Self::read_code(reader, code, depth + 1)?),
+                    }
+                }
+                Value::List(values)
+            }
+            MAP => {
+                let len = read_len(reader)?;
+                let mut pairs = Vec::new();
+                for _ in 0..len {
+                    let key = Self::read_depth(reader, depth + 1)?;
+                    let value = Self::read_depth(reader, depth + 1)?;
+                    pairs.push((key, value));
+                }
+                Value::Map(pairs)
+            }
+            _ => return Err(invalid("unsupported type code")),
+        })
+    }
+}
+
+impl From<bool> for Value {
+    fn from(bool: bool) -> Value {
+        Value::Bool(bool)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(int: i32) -> Value {
+        Value::Int(int)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(long: i64) -> Value {
+        Value::Long(long)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(float: f32) -> Value {
+        Value::Float(float)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(double: f64) -> Value {
+        Value::Double(double)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(string: &str) -> Value {
+        Value::String(string.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(string: String) -> Value {
+        Value::String(string)
+    }
+}
+
+impl From<&[u8]> for Value {
+    fn from(bytes: &[u8]) -> Value {
+        Value::Bytes(bytes.to_vec())
+    }
+}
+
+/// Reads the raw bytes of the next value in a stream into a buffer.
+///
+/// This validates only the structure of the value, so the bytes can be
+/// passed through without decoding. Unlike `Value`, this also supports
+/// serialized `Writable` and application specific types. Returns false
+/// if the stream ends before the value starts.
+pub(crate) fn read_raw<R>(reader: &mut R, buf: &mut Vec<u8>) -> io::Result<bool>
+where
+    R: Read,
+{
+    let mut code = [0; 1];
+
+    // a clean end of stream is not an error
+    if reader.read(&mut code)? == 0 {
+        return Ok(false);
+    }
+
+    buf.push(code[0]);
+    read_raw_code(reader, code[0], buf, 0)?;
+
+    Ok(true)
+}
+
+/// Validates that a slice contains exactly one typed bytes value.
+pub(crate) fn validate(mut bytes: &[u8]) -> io::Result<()> {
+    let mut buf = Vec::with_capacity(bytes.len());
+
+    if !read_raw(&mut bytes, &mut buf)? {
+        return Err(invalid("missing value"));
+    }
+
+    if !bytes.is_empty() {
+        return Err(invalid("trailing bytes after value"));
+    }
+
+    Ok(())
+}
+
+/// Reads the raw bytes of a value body with the provided type code.
+fn read_raw_code<R>(reader: &mut R, code: u8, buf: &mut Vec<u8>, depth: usize) -> io::Result<()>
+where
+    R: Read,
+{
+    if depth >= MAX_DEPTH && matches!(code, VECTOR | LIST | MAP) {
+        return Err(invalid("values nested too deeply"));
+    }
+
+    match code {
+        BYTES | STRING | WRITABLE | 100..=200 => {
+            let len = read_raw_bytes(reader, 4, buf)?;
+            let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]);
+            read_raw_bytes(reader, len as usize, buf)?;
+        }
+        BYTE | BOOL => {
+            read_raw_bytes(reader, 1, buf)?;
+        }
+        INT | FLOAT => {
+            read_raw_bytes(reader, 4, buf)?;
+        }
+        LONG | DOUBLE => {
+            read_raw_bytes(reader, 8, buf)?;
+        }
+        VECTOR | MAP => {
+            let len = read_raw_bytes(reader, 4, buf)?;
+            let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+            let len = if code == MAP { len * 2 } else { len };
+            for _ in 0..len {
+                let code = read_raw_bytes(reader, 1, buf)?[0];
+                read_raw_code(reader, code, buf, depth + 1)?;
+            }
+        }
+        LIST => loop {
+            match read_raw_bytes(reader, 1, buf)?[0] {
+                MARKER => break,
+                code => read_raw_code(reader, code, buf, depth + 1)?,
+            }
+        },
+        _ => return Err(invalid("unsupported type code")),
+    }
+    Ok(())
+}
+
+/// Reads an exact number of bytes onto a buffer, returning the new bytes.
+fn read_raw_bytes<'b, R>(reader: &mut R, len: usize, buf: &'b mut Vec<u8>) -> io::Result<&'b [u8]>
+where
+    R: Read,
+{
+    let start = buf.len();
+    reader.take(len as u64).read_to_end(buf)?;
+
+    if buf.len() - start < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(&buf[start..])
+}
+
+/// Reads a fixed size array of bytes from a stream.
+fn read_array<R, const N: usize>(reader: &mut R) -> io::Result<[u8; N]>
+where
+    R: Read,
+{
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Reads a length prefixed vector of bytes from a stream.
+fn read_vec<R>(reader: &mut R) -> io::Result<Vec<u8>>
+where
+    R: Read,
+{
+    let mut bytes = Vec::new();
+    let len = read_len(reader)?;
+    read_raw_bytes(reader, len, &mut bytes)?;
+    Ok(bytes)
+}
+
+/// Reads a length prefix from a stream.
+fn read_len<R>(reader: &mut R) -> io::Result<usize>
+where
+    R: Read,
+{
+    Ok(u32::from_be_bytes(read_array(reader)?) as usize)
+}
+
+/// Writes a length prefix to a stream.
+fn write_len<W>(writer: &mut W, len: usize) -> io::Result<()>
+where
+    W: Write,
+{
+    writer.write_all(&(len as u32).to_be_bytes())
+}
+
+/// Creates an error to represent invalid typed bytes.
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_round_trip() {
+        let values = vec![
+            Value::Bytes(b"a\tb\nc".to_vec()),
+            Value::Byte(-3),
+            Value::Bool(true),
+            Value::Int(-7),
+            Value::Long(1 << 40),
+            Value::Float(1.5),
+            Value::Double(-2.25),
+            Value::String("été".to_string()),
+            Value::Vector(vec![Value::Int(1), Value::from("two")]),
+            Value::List(vec![Value::Bool(false), Value::List(vec![])]),
+            Value::Map(vec![(Value::from("key"), Value::Long(3))]),
+        ];
+
+        for value in values {
+            let bytes = value.encode();
+            assert_eq!(Value::decode(&bytes).unwrap(), value);
+
+            let mut raw = Vec::new();
+            assert!(read_raw(&mut &bytes[..], &mut raw).unwrap());
+            assert_eq!(raw, bytes);
+        }
+    }
+
+    #[test]
+    fn test_value_encoding() {
+        assert_eq!(Value::Int(1).encode(), vec![3, 0, 0, 0, 1]);
+        assert_eq!(Value::from("hi").encode(), vec![7, 0, 0, 0, 2, b'h', b'i']);
+        assert_eq!(
+            Value::List(vec![Value::Bool(true)]).encode(),
+            vec![9, 2, 1, 255]
+        );
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        assert!(Value::decode(&[]).is_err());
+        assert!(Value::decode(&[3, 0, 0]).is_err());
+        assert!(Value::decode(&[2, 1, 0]).is_err());
+        assert!(Value::decode(&[7, 0, 0, 0, 1, 0xff]).is_err());
+        assert!(Value::decode(&[42]).is_err());
+    }
+
+    #[test]
+    fn test_validation() {
+        assert!(validate(&Value::from("a").encode()).is_ok());
+        assert!(validate(&[]).is_err());
+        assert!(validate(b"1").is_err());
+        assert!(validate(&[1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let nested = |depth: usize| {
+            let mut bytes = vec![LIST; depth];
+            bytes.extend(vec![MARKER; depth]);
+            bytes
+        };
+
+        let mut raw = Vec::new();
+
+        assert!(Value::decode(&nested(MAX_DEPTH)).is_ok());
+        assert!(read_raw(&mut &nested(MAX_DEPTH)[..], &mut raw).is_ok());
+
+        let deep = nested(100_000);
+
+        let err = Value::decode(&deep).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let err = read_raw(&mut &deep[..], &mut raw).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_raw_reading() {
+        let mut input = &[50, 0, 0, 0, 1, 9, 3, 0, 0, 0, 2, 7][..];
+        let mut raw = Vec::new();
+
+        assert!(read_raw(&mut input, &mut raw).unwrap());
+        assert_eq!(raw, vec![50, 0, 0, 0, 1, 9]);
+
+        raw.clear();
+
+        assert!(read_raw(&mut input, &mut raw).unwrap());
+        assert_eq!(raw, vec![3, 0, 0, 0, 2]);
+
+        raw.clear();
+
+        assert!(read_raw(&mut input, &mut raw).is_err());
+        assert!(!read_raw(&mut input, &mut raw).unwrap());
+    }
+}
@@ -0,0 +1,175 @@
+//! XML bindings to read fragments of an input as records.
+use std::io::{self, BufRead};
+
+use super::recovery::Errors;
+use crate::context::Configuration;
+
+/// Markers structure to represent the bounds of an XML fragment.
+///
+/// This mirrors the `StreamXmlRecordReader` of Hadoop Streaming, which
+/// is enabled for mapping stages by setting `stream.recordreader.begin`
+/// and `stream.recordreader.end` (e.g. `<page>` and `</page>`). Each
+/// fragment includes both markers, and any bytes between fragments are
+/// discarded.
+#[derive(Clone, Debug)]
+pub(crate) struct Markers {
+    begin: Vec<u8>,
+    end: Vec<u8>,
+}
+
+impl Markers {
+    /// Creates new `Markers` from a job `Configuration`, if enabled.
+    pub(crate) fn new(conf: &Configuration) -> Option<Self> {
+        if conf.get("mapreduce.task.ismap") != Some("true") {
+            return None;
+        }
+
+        let marker = |key| {
+            conf.get(key)
+                .filter(|marker| !marker.is_empty())
+                .map(|marker| marker.as_bytes().to_vec())
+        };
+
+        Some(Self {
+            begin: marker("stream.recordreader.begin")?,
+            end: marker("stream.recordreader.end")?,
+        })
+    }
+}
+
+/// Reads the next XML fragment from a stream into a buffer.
+///
+/// The offset of the fragment within the stream is returned alongside the
+/// fragment, based on the position provided (which is moved past all bytes
+/// consumed). A fragment which is missing the end marker is discarded, as
+/// in Hadoop, and a read error always ends the stream.
+pub(crate) fn read_fragment<'b, R>(
+    input: &mut R,
+    record: &'b mut Vec<u8>,
+    markers: &Markers,
+    position: &mut usize,
+    errors: &mut Errors,
+) -> Option<(usize, &'b [u8])>
+where
+    R: BufRead + ?Sized,
+{
+    record.clear();
+
+    let mut skipped = 0;
+
+    let found = seek(input, record, &markers.begin, &mut skipped).and_then(|found| match found {
+        true => read_to(input, record, &markers.end),
+        false => Ok(false),
+    });
+
+    *position += skipped + record.len();
+
+    match found {
+        Ok(true) => Some((*position - record.len(), &record[..])),
+        Ok(false) => None,
+        Err(err) => {
+            errors.handle(err, 1, false);
+            None
+        }
+    }
+}
+
+/// Reads until a buffer ends with a marker, discarding any bytes before it.
+fn seek<R>(input: &mut R, buf: &mut Vec<u8>, marker: &[u8], skipped: &mut usize) -> io::Result<bool>
+where
+    R: BufRead + ?Sized,
+{
+    let last = marker[marker.len() - 1];
+
+    loop {
+        let read = input.read_until(last, buf)?;
+
+        // only the bytes which could be part of the marker are kept
+        let excess = if read == 0 {
+            buf.len()
+        } else {
+            buf.len().saturating_sub(marker.len())
+        };
+
+        *skipped += excess;
+        buf.drain(..excess);
+
+        if read == 0 {
+            return Ok(false);
+        }
+
+        if buf.ends_with(marker) {
+            return Ok(true);
+        }
+    }
+}
+
+/// Reads until a buffer ends with a marker, keeping all bytes read.
+fn read_to<R>(input: &mut R, buf: &mut Vec<u8>, marker: &[u8]) -> io::Result<bool>
+where
+    R: BufRead + ?Sized,
+{
+    let last = marker[marker.len() - 1];
+
+    loop {
+        if input.read_until(last, buf)? == 0 {
+            return Ok(false);
+        }
+
+        if buf.ends_with(marker) {
+            return Ok(true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markers_creation() {
+        let mut conf = Configuration::default();
+
+        conf.insert("mapreduce.task.ismap", "true");
+        conf.insert("stream.recordreader.begin", "<page>");
+
+        assert!(Markers::new(&conf).is_none());
+
+        conf.insert("stream.recordreader.end", "</page>");
+
+        assert!(Markers::new(&conf).is_some());
+
+        conf.insert("mapreduce.task.ismap", "false");
+
+        assert!(Markers::new(&conf).is_none());
+    }
+
+    #[test]
+    fn test_fragment_reading() {
+        let markers = Markers {
+            begin: b"<page>".to_vec(),
+            end: b"</page>".to_vec(),
+        };
+
+        let mut input = &b"<xml>\n<page>\n<a>1</a>\n</page> <page>2</page>\n<page>3"[..];
+        let mut record = Vec::new();
+        let mut position = 0;
+        let mut errors = Errors::default();
+
+        let mut next = || {
+            read_fragment(
+                &mut input,
+                &mut record,
+                &markers,
+                &mut position,
+                &mut errors,
+            )
+            .map(|(offset, fragment)| (offset, fragment.to_vec()))
+        };
+
+        assert_eq!(next(), Some((6, b"<page>\n<a>1</a>\n</page>".to_vec())));
+        assert_eq!(next(), Some((30, b"<page>2</page>".to_vec())));
+        assert_eq!(next(), None);
+        assert_eq!(next(), None);
+    }
+}
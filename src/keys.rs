@@ -0,0 +1,527 @@
+//! Key field specifications, as used by Hadoop's `KeyFieldHelper`.
+//!
+//! These specifications are provided as options in the same format as
+//! the UNIX `sort` command (i.e. `-k2,2 -k3.1,3.4n`), and are used to
+//! select portions of a key for both partitioning and comparison.
+use std::cmp::Ordering;
+
+use crate::context::Configuration;
+
+/// Key field structure to represent a set of key field specifications.
+#[derive(Clone, Debug)]
+pub(crate) struct KeyFields {
+    separator: Vec<u8>,
+    specs: Vec<KeySpec>,
+    seen: bool,
+}
+
+/// Key specification structure to represent a single `-k` option.
+///
+/// Field and character indices are 1-based, as with `sort`. An end
+/// field of `0` selects until the end of the key, and an end character
+/// of `0` selects until the end of the end field.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct KeySpec {
+    pub(crate) begin_field: usize,
+    pub(crate) begin_char: usize,
+    pub(crate) end_field: usize,
+    pub(crate) end_char: usize,
+    pub(crate) numeric: bool,
+    pub(crate) reverse: bool,
+}
+
+impl Default for KeySpec {
+    /// Creates a `KeySpec` selecting the entire key.
+    fn default() -> Self {
+        Self {
+            begin_field: 1,
+            begin_char: 1,
+            end_field: 0,
+            end_char: 0,
+            numeric: false,
+            reverse: false,
+        }
+    }
+}
+
+impl KeyFields {
+    /// Creates the `KeyFields` used to compare keys when sorting a job.
+    ///
+    /// This will contain the `mapreduce.partition.keycomparator.options`
+    /// when `KeyFieldBasedComparator` is set as the job comparator via the
+    /// `mapreduce.job.output.key.comparator.class` value. Otherwise there
+    /// will be no specifications, and keys are compared as raw bytes.
+    pub(crate) fn comparator(conf: &Configuration) -> Self {
+        let class = conf
+            .get("mapreduce.job.output.key.comparator.class")
+            .or_else(|| conf.get("mapred.output.key.comparator.class"))
+            .unwrap_or("");
+
+        let options = if class.ends_with("KeyFieldBasedComparator") {
+            conf.get("mapreduce.partition.keycomparator.options")
+                .unwrap_or("")
+        } else {
+            ""
+        };
+
+        Self::parse(options, separator(conf))
+    }
+
+    /// Creates a new `KeyFields` from a set of `sort` style options.
+    ///
+    /// Invalid specifications are ignored, rather than causing an error.
+    /// If no options are provided at all, there will be no specifications.
+    pub(crate) fn parse(options: &str, separator: &[u8]) -> Self {
+        let mut global = KeySpec::default();
+        let mut specs = Vec::new();
+
+        // split the options into arguments
+        let mut args = options.split_whitespace();
+
+        while let Some(arg) = args.next() {
+            match arg {
+                "-n" => global.numeric = true,
+                "-r" => global.reverse = true,
+                "-nr" | "-rn" => {
+                    global.numeric = true;
+                    global.reverse = true;
+                }
+                _ if arg.starts_with("-k") => {
+                    // the spec can be provided as a separate argument
+                    let spec = match &arg[2..] {
+                        "" => args.next().unwrap_or(""),
+                        spec => spec,
+                    };
+
+                    if let Some(spec) = parse_spec(spec) {
+                        specs.push(spec);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        let seen = !specs.is_empty();
+
+        // global options apply to any spec without options
+        for spec in &mut specs {
+            if !spec.numeric && !spec.reverse {
+                spec.numeric = global.numeric;
+                spec.reverse = global.reverse;
+            }
+        }
+
+        // global options alone apply to the entire key
+        if !seen && options.split_whitespace().next().is_some() {
+            specs.push(global);
+        }
+
+        Self {
+            separator: separator.to_vec(),
+            specs,
+            seen,
+        }
+    }
+
+    /// Creates a new `KeyFields` selecting a range of fields.
+    pub(crate) fn range(begin: usize, end: usize, separator: &[u8]) -> Self {
+        Self {
+            separator: separator.to_vec(),
+            specs: vec![KeySpec {
+                begin_field: begin,
+                end_field: end,
+                ..KeySpec::default()
+            }],
+            seen: true,
+        }
+    }
+
+    /// Returns all key specifications.
+    #[inline]
+    pub(crate) fn specs(&self) -> &[KeySpec] {
+        &self.specs
+    }
+
+    /// Compares two keys using the key specifications.
+    ///
+    /// Selected portions are compared as bytes unless marked as numeric,
+    /// and the result is inverted when marked as reversed. A key missing
+    /// a selected portion is ordered first. Keys are compared in their
+    /// entirety when there are no specifications.
+    pub(crate) fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        // no specs means a byte comparison
+        if self.specs.is_empty() {
+            return a.cmp(b);
+        }
+
+        let fields_a = self.fields(a);
+        let fields_b = self.fields(b);
+
+        for spec in &self.specs {
+            let select_a = self.select(a, &fields_a, spec);
+            let select_b = self.select(b, &fields_b, spec);
+
+            // compare the selected portions of each key
+            let ordering = match (select_a, select_b) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Less,
+                (Some(_), None) => Ordering::Greater,
+                (Some((sa, ea)), Some((sb, eb))) if spec.numeric => {
+                    compare_numeric(&a[sa..ea], &b[sb..eb])
+                }
+                (Some((sa, ea)), Some((sb, eb))) => a[sa..ea].cmp(&b[sb..eb]),
+            };
+
+            // reversed specs invert the ordering
+            let ordering = if spec.reverse {
+                ordering.reverse()
+            } else {
+                ordering
+            };
+
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+
+        Ordering::Equal
+    }
+
+    /// Returns the bounds of each field within a key.
+    ///
+    /// If no key specifications were provided with field indices, the
+    /// entire key is treated as a single field.
+    pub(crate) fn fields(&self, key: &[u8]) -> Vec<(usize, usize)> {
+        let mut fields = Vec::new();
+
+        // no fields means everything is a single field
+        if !self.seen || self.separator.is_empty() {
+            fields.push((0, key.len()));
+            return fields;
+        }
+
+        let mut start = 0;
+
+        // split on every separator found in the key
+        while let Some(n) = twoway::find_bytes(&key[start..], &self.separator) {
+            fields.push((start, start + n));
+            start += n + self.separator.len();
+        }
+
+        // a trailing separator does not start a new field
+        if start != key.len() {
+            fields.push((start, key.len()));
+        }
+
+        fields
+    }
+
+    /// Returns the bounds of a key selected by a specification.
+    ///
+    /// The `fields` must be the bounds provided by `fields` for the same
+    /// key. If the specification selects nothing, `None` is returned.
+    pub(crate) fn select(
+        &self,
+        key: &[u8],
+        fields: &[(usize, usize)],
+        spec: &KeySpec,
+    ) -> Option<(usize, usize)> {
+        // find the starting byte of the selection
+        let start = match fields.get(spec.begin_field - 1) {
+            Some(&(start, _)) if start + spec.begin_char <= key.len() => {
+                start + spec.begin_char - 1
+            }
+            _ => return None,
+        };
+
+        // find the (exclusive) ending byte of the selection
+        let end = match fields.get(spec.end_field.wrapping_sub(1)) {
+            Some(&(_, end)) if spec.end_char == 0 => end,
+            Some(&(start, _)) => (start + spec.end_char).min(key.len()),
+            None => key.len(),
+        };
+
+        Some((start, end.max(start)))
+    }
+}
+
+/// Returns the number of key fields a reduction groups values on, if set.
+///
+/// This is taken from the first `mapreduce.partition.keycomparator.options`
+/// specification of a `KeyFieldBasedComparator`, when it selects a prefix
+/// of the key fields (i.e. `-k1,1` in `-k1,1 -k2,2nr`). Any specifications
+/// which follow it then only order the values within each group.
+pub(crate) fn grouping(conf: &Configuration) -> Option<usize> {
+    let comparator = KeyFields::comparator(conf);
+
+    match comparator.specs().first() {
+        Some(spec)
+            if spec.begin_field == 1
+                && spec.begin_char == 1
+                && spec.end_field > 0
+                && spec.end_char == 0 =>
+        {
+            Some(spec.end_field)
+        }
+        _ => None,
+    }
+}
+
+/// Returns the separator used to split key fields in a job.
+pub(crate) fn separator(conf: &Configuration) -> &[u8] {
+    conf.get("mapreduce.map.output.key.field.separator")
+        .or_else(|| conf.get("map.output.key.field.separator"))
+        .unwrap_or("\t")
+        .as_bytes()
+}
+
+/// Compares two byte slices as decimal numbers.
+///
+/// Numbers are compared textually, so there is no limit on precision.
+/// Any trailing non-numeric bytes are ignored, and slices which do not
+/// begin with a number are treated as zero.
+fn compare_numeric(a: &[u8], b: &[u8]) -> Ordering {
+    let (neg_a, int_a, frac_a) = parse_numeric(a);
+    let (neg_b, int_b, frac_b) = parse_numeric(b);
+
+    // magnitude comparison of the numbers
+    let ordering = int_a
+        .len()
+        .cmp(&int_b.len())
+        .then_with(|| int_a.cmp(int_b))
+        .then_with(|| frac_a.cmp(frac_b));
+
+    match (neg_a, neg_b) {
+        (false, true) => Ordering::Greater,
+        (true, false) => Ordering::Less,
+        (true, true) => ordering.reverse(),
+        (false, false) => ordering,
+    }
+}
+
+/// Parses a decimal number into the sign, integer digits, and fraction.
+///
+/// Insignificant zeros are stripped from both sides of the number, and
+/// a negative zero is treated as a positive zero.
+fn parse_numeric(num: &[u8]) -> (bool, &[u8], &[u8]) {
+    // strip any leading sign
+    let (neg, num) = match num.first() {
+        Some(b'-') => (true, &num[1..]),
+        _ => (false, num),
+    };
+
+    // find the integer digits, without leading zeros
+    let len = num.iter().take_while(|b| b.is_ascii_digit()).count();
+    let zeros = num[..len].iter().take_while(|b| **b == b'0').count();
+    let int = &num[zeros..len];
+
+    // find the fraction digits, without trailing zeros
+    let frac = match num.get(len) {
+        Some(b'.') => {
+            let frac = &num[len + 1..];
+            let len = frac.iter().take_while(|b| b.is_ascii_digit()).count();
+            let zeros = frac[..len].iter().rev().take_while(|b| **b == b'0').count();
+            &frac[..len - zeros]
+        }
+        _ => &[],
+    };
+
+    (neg && !(int.is_empty() && frac.is_empty()), int, frac)
+}
+
+/// Parses a single key specification (i.e. `2.3n,4r`).
+fn parse_spec(spec: &str) -> Option<KeySpec> {
+    let mut key = KeySpec::default();
+
+    // split the begin and end positions
+    let mut parts = spec.splitn(2, ',');
+
+    // parse the beginning position, which is required
+    let (field, chr) = parse_position(parts.next()?, &mut key)?;
+
+    if field == 0 || chr == Some(0) {
+        return None;
+    }
+
+    key.begin_field = field;
+    key.begin_char = chr.unwrap_or(1);
+
+    // parse the ending position, if any
+    if let Some(end) = parts.next() {
+        let (field, chr) = parse_position(end, &mut key)?;
+
+        if field == 0 {
+            return None;
+        }
+
+        key.end_field = field;
+        key.end_char = chr.unwrap_or(0);
+    }
+
+    Some(key)
+}
+
+/// Parses a single position inside a key specification (i.e. `2.3nr`).
+///
+/// Any options trailing the position are applied to the provided spec.
+fn parse_position(position: &str, spec: &mut KeySpec) -> Option<(usize, Option<usize>)> {
+    // strip any trailing options from the position
+    let trimmed = position.trim_end_matches(&['n', 'r'][..]);
+
+    for opt in position[trimmed.len()..].chars() {
+        match opt {
+            'n' => spec.numeric = true,
+            _ => spec.reverse = true,
+        }
+    }
+
+    // split the field and character indices
+    let mut parts = trimmed.splitn(2, '.');
+
+    let field = parts.next()?.parse().ok()?;
+    let chr = match parts.next() {
+        Some(chr) => Some(chr.parse().ok()?),
+        None => None,
+    };
+
+    Some((field, chr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spec_parsing() {
+        let keys = KeyFields::parse("-k1,1 -k2.3nr,3.4 -k 4 -kx -nr", b"\t");
+
+        assert_eq!(
+            keys.specs(),
+            &[
+                KeySpec {
+                    begin_field: 1,
+                    begin_char: 1,
+                    end_field: 1,
+                    end_char: 0,
+                    numeric: true,
+                    reverse: true,
+                },
+                KeySpec {
+                    begin_field: 2,
+                    begin_char: 3,
+                    end_field: 3,
+                    end_char: 4,
+                    numeric: true,
+                    reverse: true,
+                },
+                KeySpec {
+                    begin_field: 4,
+                    begin_char: 1,
+                    end_field: 0,
+                    end_char: 0,
+                    numeric: true,
+                    reverse: true,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_global_options() {
+        let keys = KeyFields::parse("-n", b"\t");
+
+        assert_eq!(
+            keys.specs(),
+            &[KeySpec {
+                numeric: true,
+                ..KeySpec::default()
+            }]
+        );
+
+        assert!(KeyFields::parse("", b"\t").specs().is_empty());
+    }
+
+    #[test]
+    fn test_key_comparison() {
+        let keys = KeyFields::parse("-k1,1 -k2,2nr", b"\t");
+
+        let mut input = vec![
+            &b"b\t1"[..],
+            b"a\t10",
+            b"a\t9.5",
+            b"a\t-3",
+            b"b\t02",
+            b"a",
+            b"a\t0010",
+        ];
+
+        input.sort_by(|a, b| keys.compare(a, b));
+
+        assert_eq!(
+            input,
+            vec![
+                &b"a\t10"[..],
+                b"a\t0010",
+                b"a\t9.5",
+                b"a\t-3",
+                b"a",
+                b"b\t02",
+                b"b\t1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_numeric_comparison() {
+        assert_eq!(compare_numeric(b"10", b"9"), Ordering::Greater);
+        assert_eq!(compare_numeric(b"-10", b"-9"), Ordering::Less);
+        assert_eq!(compare_numeric(b"-0", b"0.000"), Ordering::Equal);
+        assert_eq!(compare_numeric(b"1.05", b"1.5"), Ordering::Less);
+        assert_eq!(compare_numeric(b"1.50", b"1.5"), Ordering::Equal);
+        assert_eq!(compare_numeric(b"abc", b"0"), Ordering::Equal);
+        assert_eq!(compare_numeric(b"-1", b"abc"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_comparator_config() {
+        let env = vec![
+            ("mapreduce.partition.keycomparator.options", "-k2,2n"),
+            ("mapreduce.map.output.key.field.separator", "."),
+        ];
+
+        let conf = Configuration::with_env(env.clone().into_iter());
+
+        assert!(KeyFields::comparator(&conf).specs().is_empty());
+
+        let mut env = env;
+        env.push((
+            "mapreduce.job.output.key.comparator.class",
+            "org.apache.hadoop.mapreduce.lib.partition.KeyFieldBasedComparator",
+        ));
+
+        let conf = Configuration::with_env(env.into_iter());
+        let keys = KeyFields::comparator(&conf);
+
+        assert_eq!(keys.specs().len(), 1);
+        assert_eq!(keys.compare(b"a.10", b"b.9"), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_field_selection() {
+        let keys = KeyFields::parse("-k2,2 -k1.2,2.2 -k3 -k5,5", b".");
+        let key = b"ab.cd.ef";
+        let fields = keys.fields(key);
+
+        assert_eq!(fields, vec![(0, 2), (3, 5), (6, 8)]);
+
+        let select = |idx: usize| {
+            keys.select(key, &fields, &keys.specs()[idx])
+                .map(|(start, end)| &key[start..end])
+        };
+
+        assert_eq!(select(0), Some(&b"cd"[..]));
+        assert_eq!(select(1), Some(&b"b.cd"[..]));
+        assert_eq!(select(2), Some(&b"ef"[..]));
+        assert_eq!(select(3), None);
+    }
+}
@@ -7,21 +7,32 @@
 //!
 //! Macros are provided for IO, to provide a compile-time guarantee of things
 //! such as counter/status updates, or writing to the Hadoop task logs.
-#![doc(html_root_url = "https://docs.rs/efflux/2.0.1")]
+#![doc(html_root_url = "https://docs.rs/efflux/3.0.0")]
 #[macro_use]
 pub mod macros;
 pub mod context;
+mod error;
+pub mod fallible;
 pub mod io;
+mod keys;
+pub mod local;
 pub mod mapper;
+pub mod partition;
 pub mod reducer;
+pub mod testing;
+#[cfg(feature = "serde")]
+pub mod typed;
 
 use self::mapper::Mapper;
-use self::reducer::Reducer;
+use self::reducer::{Reducer, StreamReducer};
 
 use self::mapper::MapperLifecycle;
-use self::reducer::ReducerLifecycle;
+use self::reducer::{ReducerLifecycle, StreamReducerLifecycle};
 
-use self::io::run_lifecycle;
+use self::io::{run_lifecycle, run_lifecycle_with};
+use std::io::{Read, Write};
+
+pub use self::error::Error;
 
 /// Executes a `Mapper` against the current `stdin`.
 #[inline]
@@ -41,6 +52,54 @@ where
     run_lifecycle(ReducerLifecycle::new(reducer));
 }
 
+/// Executes a `StreamReducer` against the current `stdin`.
+#[inline]
+pub fn run_stream_reducer<R>(reducer: R)
+where
+    R: StreamReducer + 'static,
+{
+    run_lifecycle(StreamReducerLifecycle::new(reducer));
+}
+
+/// Executes a `Mapper` against a custom input and output.
+///
+/// Returns an `Error` if the stage fails, rather than exiting.
+#[inline]
+pub fn run_mapper_with<M, I, O>(input: I, output: O, mapper: M) -> Result<(), Error>
+where
+    M: Mapper + 'static,
+    I: Read,
+    O: Write,
+{
+    run_lifecycle_with(input, output, MapperLifecycle::new(mapper))
+}
+
+/// Executes a `Reducer` against a custom input and output.
+///
+/// Returns an `Error` if the stage fails, rather than exiting.
+#[inline]
+pub fn run_reducer_with<R, I, O>(input: I, output: O, reducer: R) -> Result<(), Error>
+where
+    R: Reducer + 'static,
+    I: Read,
+    O: Write,
+{
+    run_lifecycle_with(input, output, ReducerLifecycle::new(reducer))
+}
+
+/// Executes a `StreamReducer` against a custom input and output.
+///
+/// Returns an `Error` if the stage fails, rather than exiting.
+#[inline]
+pub fn run_stream_reducer_with<R, I, O>(input: I, output: O, reducer: R) -> Result<(), Error>
+where
+    R: StreamReducer + 'static,
+    I: Read,
+    O: Write,
+{
+    run_lifecycle_with(input, output, StreamReducerLifecycle::new(reducer))
+}
+
 // prelude module
 pub mod prelude {
     //! A "prelude" for crates using the `efflux` crate.
@@ -56,5 +115,5 @@ pub mod prelude {
     pub use super::context::{Configuration, Context, Contextual};
     pub use super::log;
     pub use super::mapper::Mapper;
-    pub use super::reducer::Reducer;
+    pub use super::reducer::{Reducer, StreamReducer, Values};
 }
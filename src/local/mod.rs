@@ -0,0 +1,790 @@
+//! Local execution of MapReduce jobs, without the need for Hadoop.
+//!
+//! This module offers the `LocalJob` runner, which executes a `Mapper`,
+//! an optional combining `Reducer`, and a `Reducer` inside the current
+//! process. The output of the mapping stage is sorted and grouped by key
+//! in the same way as the Hadoop shuffle, so the output should match that
+//! of a job executed on a cluster. This replaces the need to simulate a
+//! job via a UNIX pipeline, making it simple to test an entire job.
+//!
+//! Output of the mapping stage is buffered in memory up to the limit set
+//! by `mapreduce.task.io.sort.mb`, after which sorted runs are spilled to
+//! disk and merged back together, so inputs can be larger than memory.
+//! Jobs with several reducers assign keys to partitions via a type from
+//! the `partition` module, with each partition being written separately:
+//!
+//! ```rust
+//! # extern crate efflux;
+//! use efflux::local::LocalJob;
+//! use efflux::prelude::*;
+//!
+//! // emit every word with a count of 1
+//! let mapper = |_key: usize, value: &[u8], ctx: &mut Context| {
+//!     for word in value.split(|b| *b == b' ') {
+//!         ctx.write(word, b"1");
+//!     }
+//! };
+//!
+//! // emit every word with the number of appearances
+//! let reducer = |key: &[u8], values: &[&[u8]], ctx: &mut Context| {
+//!     ctx.write(key, values.len().to_string().as_bytes());
+//! };
+//!
+//! // output buffer
+//! let mut output = Vec::new();
+//!
+//! // run the job against an in-memory input
+//! LocalJob::new(mapper, reducer)
+//!     .input_reader(&b"one two\ntwo\n"[..])
+//!     .run(&mut output)
+//!     .expect("job failed");
+//!
+//! // check the output is sorted and grouped
+//! assert_eq!(output, b"one\t1\ntwo\t2\n");
+//! ```
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::context::{Configuration, Context, Delimiters, InputSplit, Offset, Reports};
+use crate::error::Error;
+use crate::io::{run_entries, Format, Lifecycle};
+use crate::keys;
+use crate::mapper::{Mapper, MapperLifecycle};
+use crate::partition::{self, KeyFieldBasedPartitioner, Partitioner};
+use crate::reducer::{Reducer, ReducerLifecycle};
+
+mod shuffle;
+mod spill;
+
+use self::shuffle::Shuffle;
+
+/// Local job structure to run all stages of a MapReduce job in process.
+///
+/// All inputs are fed through a single mapping stage in the order they
+/// were added, much like `cat` would when simulating a job in a shell.
+///
+/// The number of reducers is taken from `mapreduce.job.reduces` unless
+/// set explicitly, and keys are assigned to reducers by a `Partitioner`.
+/// As a single `Reducer` is used for all partitions, the setup and cleanup
+/// handlers of the `Reducer` are called once for each partition. A job with
+/// zero reducers is a map-only job, writing mapper output without sorting.
+/// As the shuffle operates on text, jobs with reducers must use the text
+/// `Format` for both the mapper output and the reducer input.
+///
+/// Records are sorted on the entire key, using the comparator set in the
+/// job `Configuration`. Reducers can be made to group on a prefix of the
+/// key fields via `grouping`, which allows for a secondary sort of the
+/// values within each group.
+pub struct LocalJob<M, R>
+where
+    M: Mapper,
+    R: Reducer,
+{
+    conf: Configuration,
+    mapper: M,
+    reducer: R,
+    reducers: Option<usize>,
+    grouping: Option<usize>,
+    combiner: Option<Box<dyn Lifecycle>>,
+    partitioner: Option<Box<dyn Partitioner>>,
+    inputs: Vec<Input>,
+}
+
+/// Input enum to represent the sources of a `LocalJob`.
+enum Input {
+    Path(PathBuf),
+    Reader(Box<dyn Read>),
+}
+
+/// Output enum to represent the destination of a `LocalJob`.
+enum Output<'o> {
+    Stream(&'o mut dyn Write),
+    Directory(PathBuf),
+}
+
+impl<M, R> LocalJob<M, R>
+where
+    M: Mapper,
+    R: Reducer,
+{
+    /// Constructs a new `LocalJob` from a `Mapper` and `Reducer`.
+    ///
+    /// The job `Configuration` is created from the environment, in the
+    /// same way it is when running on a cluster.
+    pub fn new(mapper: M, reducer: R) -> Self {
+        Self {
+            mapper,
+            reducer,
+            conf: Configuration::new(),
+            reducers: None,
+            grouping: None,
+            combiner: None,
+            partitioner: None,
+            inputs: Vec::new(),
+        }
+    }
+
+    /// Sets a `Reducer` to use as a combiner for the mapping stage.
+    pub fn combiner<C>(mut self, combiner: C) -> Self
+    where
+        C: Reducer + 'static,
+    {
+        self.combiner = Some(Box::new(ReducerLifecycle::new(combiner)));
+        self
+    }
+
+    /// Sets the job `Configuration` provided to all stages.
+    pub fn config(mut self, conf: Configuration) -> Self {
+        self.conf = conf;
+        self
+    }
+
+    /// Sets the number of key fields the `Reducer` groups values on.
+    ///
+    /// If unset, this is taken from the first specification within the
+    /// `mapreduce.partition.keycomparator.options` of the job (see the
+    /// `Delimiters` type), defaulting to all fields of the key. Unless a
+    /// `Partitioner` is configured, keys are partitioned on the grouped
+    /// fields so that each group is sent to a single reducer.
+    pub fn grouping(mut self, fields: usize) -> Self {
+        self.grouping = Some(fields);
+        self
+    }
+
+    /// Adds an input file to the job.
+    pub fn input<P>(mut self, path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.inputs.push(Input::Path(path.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Adds an input stream to the job.
+    pub fn input_reader<I>(mut self, input: I) -> Self
+    where
+        I: Read + 'static,
+    {
+        self.inputs.push(Input::Reader(Box::new(input)));
+        self
+    }
+
+    /// Sets the `Partitioner` used to assign keys to reducers.
+    ///
+    /// If unset, the `Partitioner` is taken from the job `Configuration`.
+    pub fn partitioner<P>(mut self, partitioner: P) -> Self
+    where
+        P: Partitioner + 'static,
+    {
+        self.partitioner = Some(Box::new(partitioner));
+        self
+    }
+
+    /// Sets the number of reducers used by the job.
+    pub fn reducers(mut self, reducers: usize) -> Self {
+        self.reducers = Some(reducers);
+        self
+    }
+
+    /// Executes the job, writing all output to the provided output.
+    ///
+    /// The output of each partition is written in order of partition.
+    pub fn run<O>(self, mut output: O) -> io::Result<()>
+    where
+        O: Write,
+    {
+        self.execute(Output::Stream(&mut output), &mut None)
+    }
+
+    /// Executes the job, recording all counter and status updates.
+    ///
+    /// Updates made by every stage of the job are recorded into the same
+    /// `Reports`, including any updates made before a stage failed.
+    pub(crate) fn run_reporting<O>(self, mut output: O, reports: &mut Reports) -> io::Result<()>
+    where
+        O: Write,
+    {
+        let mut recording = Some(mem::take(reports));
+        let result = self.execute(Output::Stream(&mut output), &mut recording);
+
+        *reports = recording.unwrap_or_default();
+
+        result
+    }
+
+    /// Executes the job, writing all output to a directory.
+    ///
+    /// As with Hadoop, the output of each partition is written to a file
+    /// named `part-00000` (and so on), and a `_SUCCESS` marker is written
+    /// once the job has completed.
+    pub fn run_to<P>(self, dir: P) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let dir = dir.as_ref();
+
+        fs::create_dir_all(dir)?;
+        self.execute(Output::Directory(dir.to_path_buf()), &mut None)?;
+
+        File::create(dir.join("_SUCCESS")).map(|_| ())
+    }
+
+    /// Executes all stages of the job against the provided output.
+    ///
+    /// When `reports` is set, it is moved into the `Context` of each stage
+    /// in turn, so that updates are recorded across all stages.
+    fn execute(self, mut output: Output, reports: &mut Option<Reports>) -> io::Result<()> {
+        // determine how many reducers should be used
+        let reducers = match self.reducers {
+            Some(reducers) => reducers,
+            None => self.conf.get_parsed("mapreduce.job.reduces").unwrap_or(1),
+        };
+
+        // determine how many key fields reducers group on, if any
+        let grouping = self.grouping.or_else(|| keys::grouping(&self.conf));
+
+        // determine which partitioner should be used
+        let partitioner: Rc<dyn Partitioner> = match (self.partitioner, grouping) {
+            (Some(partitioner), _) => Rc::from(partitioner),
+            (None, Some(fields)) if !partition::is_configured(&self.conf) => {
+                Rc::new(KeyFieldBasedPartitioner::prefix(&self.conf, fields))
+            }
+            (None, _) => Rc::from(partition::from_config(&self.conf)),
+        };
+
+        // create the configuration for each stage
+        let mut map_conf = self.conf.clone();
+        let mut reduce_conf = self.conf;
+
+        map_conf.insert("mapreduce.task.ismap", "true");
+        reduce_conf.insert("mapreduce.task.ismap", "false");
+
+        map_conf.insert("mapreduce.job.reduces", &*reducers.to_string());
+        reduce_conf.insert("mapreduce.job.reduces", &*reducers.to_string());
+
+        // map-only jobs write straight to the output
+        if reducers == 0 {
+            return run_mapper(self.mapper, self.inputs, map_conf, output.open(0)?, reports);
+        }
+
+        // the shuffle only understands text, so check the stages agree
+        if Format::output(&map_conf) != Format::Text || Format::input(&reduce_conf) != Format::Text
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "local shuffle requires a text format",
+            ));
+        }
+
+        // create a shuffle to sort the output of the mapping stage
+        let mut shuffle = Shuffle::new(&map_conf, partitioner.clone(), reducers);
+
+        run_mapper(
+            self.mapper,
+            self.inputs,
+            map_conf.clone(),
+            &mut shuffle,
+            reports,
+        )?;
+
+        // combiners run against the sorted mapper output
+        if let Some(mut combiner) = self.combiner {
+            let mut records = shuffle.into_records()?;
+
+            // create a shuffle to sort the output of the combiner
+            shuffle = Shuffle::new(&map_conf, partitioner, reducers);
+
+            // combiners read the map output, grouping as a reducer would
+            let mut delim = Delimiters::combiner(&map_conf);
+
+            if let Some(fields) = self.grouping {
+                delim.group(fields);
+            }
+
+            // combiners run inside the mapping stage on a cluster
+            let mut ctx = Context::with_config(map_conf, &mut shuffle);
+            ctx.insert(delim);
+
+            lend(&mut ctx, reports);
+            combiner.on_start(&mut ctx);
+
+            for partition in 0..reducers {
+                if !ctx.failed() {
+                    records.feed(partition, &mut *combiner, &mut ctx)?;
+                }
+            }
+
+            if !ctx.failed() {
+                combiner.on_end(&mut ctx);
+            }
+
+            ctx.flush();
+            reclaim(&mut ctx, reports);
+            check(&mut ctx)?;
+        }
+
+        let mut records = shuffle.into_records()?;
+        let mut reducer = ReducerLifecycle::new(self.reducer);
+
+        // run a reduction for every partition
+        for partition in 0..reducers {
+            let mut conf = reduce_conf.clone();
+            conf.insert("mapreduce.task.partition", &*partition.to_string());
+
+            let mut ctx = Context::with_config(conf, output.open(partition)?);
+
+            // explicit grouping overrides the key fields of the reducer
+            if let Some(fields) = self.grouping {
+                ctx.get_mut::<Delimiters>().unwrap().group(fields);
+            }
+
+            lend(&mut ctx, reports);
+            reducer.on_start(&mut ctx);
+
+            if !ctx.failed() {
+                records.feed(partition, &mut reducer, &mut ctx)?;
+            }
+
+            if !ctx.failed() {
+                reducer.on_end(&mut ctx);
+            }
+
+            ctx.flush();
+            reclaim(&mut ctx, reports);
+            check(&mut ctx)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Output<'_> {
+    /// Opens the output for the provided partition.
+    fn open(&mut self, partition: usize) -> io::Result<Box<dyn Write + '_>> {
+        match self {
+            Output::Stream(stream) => Ok(Box::new(&mut **stream)),
+            Output::Directory(dir) => {
+                let path = dir.join(format!("part-{:05}", partition));
+                let file = File::create(path)?;
+
+                Ok(Box::new(file))
+            }
+        }
+    }
+}
+
+/// Executes a `Mapper` against all inputs, writing to the provided output.
+fn run_mapper<M, O>(
+    mapper: M,
+    inputs: Vec<Input>,
+    conf: Configuration,
+    output: O,
+    reports: &mut Option<Reports>,
+) -> io::Result<()>
+where
+    M: Mapper,
+    O: Write,
+{
+    let mut ctx = Context::with_config(conf, output);
+    let mut mapper = MapperLifecycle::new(mapper);
+
+    lend(&mut ctx, reports);
+    mapper.on_start(&mut ctx);
+
+    // feed all inputs through the mapper, holding any read error
+    let mut read = Ok(());
+
+    for input in inputs {
+        if ctx.failed() || read.is_err() {
+            break;
+        }
+
+        // each input is read as a whole split, as a separate task would be
+        ctx.insert(Offset::new());
+
+        read = match input {
+            Input::Path(path) => File::open(&path).and_then(|file| {
+                let length = file.metadata()?.len() as usize;
+
+                ctx.insert(InputSplit::with_file(path.to_string_lossy(), 0, length));
+                run_entries(file, &mut mapper, &mut ctx)
+            }),
+            Input::Reader(reader) => {
+                ctx.insert(InputSplit::default());
+                run_entries(reader, &mut mapper, &mut ctx)
+            }
+        };
+    }
+
+    if !ctx.failed() && read.is_ok() {
+        mapper.on_end(&mut ctx);
+    }
+
+    // counters and reports are kept, even after a read error
+    ctx.flush();
+    reclaim(&mut ctx, reports);
+
+    read?;
+    check(&mut ctx)?;
+
+    Ok(())
+}
+
+/// Moves any `Reports` being recorded into the `Context` of a stage.
+fn lend(ctx: &mut Context, reports: &mut Option<Reports>) {
+    if let Some(reports) = reports.take() {
+        ctx.insert(reports);
+    }
+}
+
+/// Moves any `Reports` being recorded back out of the `Context` of a stage.
+fn reclaim(ctx: &mut Context, reports: &mut Option<Reports>) {
+    if let Some(recorded) = ctx.take::<Reports>() {
+        *reports = Some(recorded);
+    }
+}
+
+/// Checks whether a stage has failed, converting the failure to an error.
+///
+/// IO failures keep the kind of the underlying error, so callers are able
+/// to tell them apart (e.g. an invalid partition from a missing file).
+fn check(ctx: &mut Context) -> io::Result<()> {
+    let err = match ctx.take_failure() {
+        Some(err) => err,
+        None => return Ok(()),
+    };
+
+    let kind = match &err {
+        Error::Read(inner) | Error::Write(inner) => inner.kind(),
+        _ => io::ErrorKind::Other,
+    };
+
+    Err(io::Error::new(kind, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::partition::HashPartitioner;
+
+    #[test]
+    fn test_local_job() {
+        let mut output = Vec::new();
+
+        LocalJob::new(TestMapper, TestReducer)
+            .config(test_config())
+            .input_reader(&b"one two three\nthree two\nthree\n"[..])
+            .run(&mut output)
+            .unwrap();
+
+        assert_eq!(output, b"one\t1\nthree\t3\ntwo\t2\n");
+    }
+
+    #[test]
+    fn test_local_job_combiner() {
+        let mut output = Vec::new();
+
+        LocalJob::new(TestMapper, TestReducer)
+            .config(test_config())
+            .combiner(TestReducer)
+            .input_reader(&b"one two three\n"[..])
+            .input_reader(&b"three two\nthree\n"[..])
+            .run(&mut output)
+            .unwrap();
+
+        assert_eq!(output, b"one\t1\nthree\t3\ntwo\t2\n");
+    }
+
+    #[test]
+    fn test_local_job_combiner_key_fields() {
+        let mut conf = test_config();
+        conf.insert("stream.num.map.output.key.fields", "2");
+
+        let mapper = |_key: usize, value: &[u8], ctx: &mut Context| {
+            let key = value
+                .iter()
+                .map(|b| if *b == b' ' { b'\t' } else { *b })
+                .collect::<Vec<_>>();
+
+            ctx.write(&key, b"1");
+        };
+
+        let mut output = Vec::new();
+
+        LocalJob::new(mapper, TestReducer)
+            .config(conf)
+            .combiner(TestReducer)
+            .input_reader(&b"a x\na y\na y\n"[..])
+            .run(&mut output)
+            .unwrap();
+
+        assert_eq!(output, b"a\tx\t1\na\ty\t2\n");
+    }
+
+    #[test]
+    fn test_local_job_empty_input() {
+        let mut output = Vec::new();
+
+        LocalJob::new(TestMapper, TestReducer)
+            .config(test_config())
+            .run(&mut output)
+            .unwrap();
+
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_local_job_failure() {
+        let reducer = |_key: &[u8], _values: &[&[u8]], ctx: &mut Context| {
+            ctx.fail("bad group");
+        };
+
+        let result = LocalJob::new(TestMapper, reducer)
+            .config(test_config())
+            .input_reader(&b"one two\n"[..])
+            .run(io::sink());
+
+        assert_eq!(result.unwrap_err().to_string(), "task failed: bad group");
+    }
+
+    #[test]
+    fn test_local_job_missing_input() {
+        let result = LocalJob::new(TestMapper, TestReducer)
+            .config(test_config())
+            .input("/efflux/missing/input.txt")
+            .run(io::sink());
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_local_job_partitioning() {
+        let mut output = Vec::new();
+
+        LocalJob::new(TestMapper, TestReducer)
+            .config(test_config())
+            .reducers(2)
+            .partitioner(|key: &[u8], _: &[u8], _: usize| (key[0] == b'o') as usize)
+            .input_reader(&b"one two three\nthree two\nthree\n"[..])
+            .run(&mut output)
+            .unwrap();
+
+        assert_eq!(output, b"three\t3\ntwo\t2\none\t1\n");
+    }
+
+    #[test]
+    fn test_local_job_illegal_partition() {
+        let result = LocalJob::new(TestMapper, TestReducer)
+            .config(test_config())
+            .reducers(2)
+            .partitioner(|_: &[u8], _: &[u8], partitions: usize| partitions)
+            .input_reader(&b"one two\n"[..])
+            .run(io::sink());
+
+        let err = result.unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("illegal partition for one (2)"));
+    }
+
+    #[test]
+    fn test_local_job_output_directory() {
+        let dir = std::env::temp_dir().join(format!("efflux-test-{}", std::process::id()));
+
+        LocalJob::new(TestMapper, TestReducer)
+            .config(test_config())
+            .reducers(3)
+            .input_reader(&b"one two three\nthree two\nthree\n"[..])
+            .run_to(&dir)
+            .unwrap();
+
+        let mut output = Vec::new();
+
+        for partition in 0..3 {
+            let path = dir.join(format!("part-{:05}", partition));
+            let data = fs::read(path).unwrap();
+
+            for line in data.split(|b| *b == b'\n').filter(|l| !l.is_empty()) {
+                let key = &line[..line.iter().position(|b| *b == b'\t').unwrap()];
+                assert_eq!(HashPartitioner.partition(key, b"", 3), partition);
+            }
+
+            output.extend(data);
+        }
+
+        assert!(dir.join("_SUCCESS").exists());
+        assert_eq!(output.len(), b"one\t1\nthree\t3\ntwo\t2\n".len());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_local_job_map_only() {
+        let mut output = Vec::new();
+
+        LocalJob::new(TestMapper, TestReducer)
+            .config(test_config())
+            .reducers(0)
+            .input_reader(&b"one two\none\n"[..])
+            .run(&mut output)
+            .unwrap();
+
+        assert_eq!(output, b"one\t1\ntwo\t1\none\t1\n");
+    }
+
+    #[test]
+    fn test_local_job_input_split() {
+        let path = std::env::temp_dir().join(format!("efflux-split-{}.txt", std::process::id()));
+        let mut output = Vec::new();
+
+        fs::write(&path, "one\n").unwrap();
+
+        let result = LocalJob::new(
+            |_key, _value: &[u8], ctx: &mut Context| {
+                let split = ctx.get::<InputSplit>().unwrap().clone();
+                let file = split.file().map(|file| file.ends_with(".txt"));
+                ctx.write_fmt(format!("{:?}", file), split.length().unwrap_or(0));
+            },
+            TestReducer,
+        )
+        .reducers(0)
+        .input(&path)
+        .input_reader(&b"two\n"[..])
+        .run(&mut output);
+
+        fs::remove_file(&path).unwrap();
+
+        assert!(result.is_ok());
+        assert_eq!(output, b"Some(true)\t4\nNone\t0\n");
+    }
+
+    #[test]
+    fn test_local_job_binary_shuffle() {
+        let env = vec![("stream.map.output", "typedbytes")];
+
+        let result = LocalJob::new(TestMapper, TestReducer)
+            .config(Configuration::with_env(env.into_iter()))
+            .input_reader(&b"one two\n"[..])
+            .run(io::sink());
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_local_job_secondary_sort() {
+        let env = vec![
+            ("stream.num.map.output.key.fields", "2"),
+            ("mapreduce.partition.keycomparator.options", "-k1,1 -k2,2nr"),
+            (
+                "mapreduce.job.output.key.comparator.class",
+                "org.apache.hadoop.mapreduce.lib.partition.KeyFieldBasedComparator",
+            ),
+        ];
+
+        let mut output = Vec::new();
+
+        // emit the user and timestamp as the key, with the event as the value
+        let mapper = |_: usize, value: &[u8], ctx: &mut Context| ctx.write(value, b"event");
+
+        // emit the timestamps of each user, in order
+        let reducer = |key: &[u8], values: &[&[u8]], ctx: &mut Context| {
+            let stamps = values
+                .iter()
+                .map(|value| value.split(|b| *b == b'\t').next().unwrap())
+                .collect::<Vec<_>>();
+            ctx.write(key, &stamps.join(&b',')[..]);
+        };
+
+        LocalJob::new(mapper, reducer)
+            .config(Configuration::with_env(env.into_iter()))
+            .grouping(1)
+            .input_reader(&b"b\t3\nb\t20\na\t1\nb\t100\na\t5\n"[..])
+            .run(&mut output)
+            .unwrap();
+
+        assert_eq!(output, b"a\t5,1\nb\t100,20,3\n");
+    }
+
+    #[test]
+    fn test_local_job_grouping_partitions() {
+        let env = vec![
+            ("stream.num.map.output.key.fields", "2"),
+            ("mapreduce.partition.keycomparator.options", "-k1,1 -k2,2n"),
+            (
+                "mapreduce.job.output.key.comparator.class",
+                "org.apache.hadoop.mapreduce.lib.partition.KeyFieldBasedComparator",
+            ),
+        ];
+
+        let input = b"a\t3\nb\t2\nc\t1\na\t1\nd\t4\nb\t9\nc\t5\na\t2\nd\t1\n";
+
+        // group explicitly, and via the comparator options
+        for grouping in &[Some(1), None] {
+            let mut output = Vec::new();
+
+            let mapper = |_: usize, value: &[u8], ctx: &mut Context| ctx.write(value, b"");
+            let reducer = |key: &[u8], values: &[&[u8]], ctx: &mut Context| {
+                let stamps = values
+                    .iter()
+                    .map(|value| value.split(|b| *b == b'\t').next().unwrap())
+                    .collect::<Vec<_>>();
+                ctx.write(key, &stamps.join(&b',')[..]);
+            };
+
+            let mut job = LocalJob::new(mapper, reducer)
+                .config(Configuration::with_env(env.clone().into_iter()))
+                .reducers(3)
+                .input_reader(&input[..]);
+
+            if let Some(fields) = grouping {
+                job = job.grouping(*fields);
+            }
+
+            job.run(&mut output).unwrap();
+
+            // every group is emitted once, in full
+            let mut lines = output
+                .split(|b| *b == b'\n')
+                .filter(|line| !line.is_empty())
+                .collect::<Vec<_>>();
+
+            lines.sort();
+
+            assert_eq!(
+                lines,
+                vec![&b"a\t1,2,3"[..], b"b\t2,9", b"c\t1,5", b"d\t1,4"]
+            );
+        }
+    }
+
+    fn test_config() -> Configuration {
+        Configuration::with_env(Vec::<(String, String)>::new().into_iter())
+    }
+
+    struct TestMapper;
+    struct TestReducer;
+
+    impl Mapper for TestMapper {
+        fn map(&mut self, _key: usize, value: &[u8], ctx: &mut Context) {
+            for word in value.split(|b| *b == b' ') {
+                ctx.write(word, b"1");
+            }
+        }
+    }
+
+    impl Reducer for TestReducer {
+        fn reduce(&mut self, key: &[u8], values: &[&[u8]], ctx: &mut Context) {
+            let mut count = 0;
+            for value in values {
+                count += std::str::from_utf8(value)
+                    .unwrap()
+                    .parse::<usize>()
+                    .unwrap();
+            }
+            ctx.write_fmt(std::str::from_utf8(key).unwrap(), count);
+        }
+    }
+}
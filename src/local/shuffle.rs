@@ -0,0 +1,473 @@
+//! Shuffle bindings to partition and sort stage output by key.
+use std::env;
+use std::io::{self, Write};
+use std::mem;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use super::spill::{Merge, Spill};
+use crate::context::{Configuration, Context, Delimiters};
+use crate::io::Lifecycle;
+use crate::keys::KeyFields;
+use crate::partition::Partitioner;
+
+/// Shuffle structure to buffer, partition, and sort the output of a stage.
+///
+/// All output written to a `Shuffle` is split into records on each
+/// newline, and each record is split into a key/value pair using the
+/// output delimiters of the writing stage. Records are stored inside
+/// a single contiguous buffer to avoid allocating for every pair.
+///
+/// Keys are compared as raw bytes, unless `KeyFieldBasedComparator` is
+/// configured as the job comparator; in this case the key is compared
+/// using `mapreduce.partition.keycomparator.options` (i.e. `-k2,2nr`).
+///
+/// Once the buffer grows beyond `mapreduce.task.io.sort.mb`, records
+/// are sorted and spilled to disk inside `mapreduce.cluster.local.dir`
+/// (or the system temporary directory). All spills are then merged back
+/// together when the records are read back out of the `Shuffle`.
+pub(crate) struct Shuffle {
+    delim: Delimiters,
+    comparator: Rc<KeyFields>,
+    partitioner: Rc<dyn Partitioner>,
+    partitions: usize,
+    buffer: Vec<u8>,
+    records: Vec<Record>,
+    offset: usize,
+    limit: usize,
+    factor: usize,
+    dir: PathBuf,
+    spills: Vec<Spill>,
+}
+
+/// Record structure to store the bounds of a key/value pair.
+struct Record {
+    partition: usize,
+    start: usize,
+    key: usize,
+    value: usize,
+    end: usize,
+}
+
+impl Shuffle {
+    /// Creates a new `Shuffle` using the configuration of the writing stage.
+    pub(crate) fn new(
+        conf: &Configuration,
+        partitioner: Rc<dyn Partitioner>,
+        partitions: usize,
+    ) -> Self {
+        // buffer size in megabytes, defaulting to the Hadoop default
+        let limit = conf
+            .get_parsed::<usize>("mapreduce.task.io.sort.mb")
+            .unwrap_or(100);
+
+        // number of spills to merge at once
+        let factor = conf
+            .get_parsed::<usize>("mapreduce.task.io.sort.factor")
+            .unwrap_or(10);
+
+        // spills go to the first local directory, if any
+        let dir = conf
+            .get_list("mapreduce.cluster.local.dir")
+            .and_then(|dirs| dirs.first().map(PathBuf::from))
+            .unwrap_or_else(env::temp_dir);
+
+        Self {
+            delim: Delimiters::new(conf),
+            comparator: Rc::new(KeyFields::comparator(conf)),
+            partitioner,
+            partitions,
+            buffer: Vec::new(),
+            records: Vec::new(),
+            offset: 0,
+            limit: limit * 1024 * 1024,
+            factor,
+            dir,
+            spills: Vec::new(),
+        }
+    }
+
+    /// Converts the `Shuffle` into a sorted stream of `Records`.
+    pub(crate) fn into_records(mut self) -> io::Result<Records> {
+        // capture any trailing record
+        if self.offset < self.buffer.len() {
+            self.push(self.offset, self.buffer.len())?;
+            self.offset = self.buffer.len();
+        }
+
+        // everything fit in memory, so read directly
+        if self.spills.is_empty() {
+            self.sort();
+
+            return Ok(Records {
+                cursor: Cursor::Memory(self, 0),
+                entry: Vec::new(),
+            });
+        }
+
+        // spill the remainder and merge all spills
+        self.spill()?;
+
+        let spills = mem::take(&mut self.spills);
+        let merge = Merge::new(spills, &self.dir, self.factor, self.comparator.clone())?;
+
+        Ok(Records {
+            cursor: Cursor::Merge(merge),
+            entry: Vec::new(),
+        })
+    }
+
+    /// Returns the partition and key/value pair of a record.
+    #[inline]
+    fn pair(&self, record: &Record) -> (usize, &[u8], &[u8]) {
+        (
+            record.partition,
+            &self.buffer[record.start..record.key],
+            &self.buffer[record.value..record.end],
+        )
+    }
+
+    /// Pushes a record into the buffer, using the provided bounds.
+    ///
+    /// As with Hadoop, a partition outside of the range of partitions is
+    /// an error, as the record would otherwise never reach a reducer.
+    fn push(&mut self, start: usize, end: usize) -> io::Result<()> {
+        // split the record into the key/value pair
+        let (key, value) = self.delim.split_output(&self.buffer[start..end]);
+
+        // find the partition the record belongs to
+        let partition = self.partitioner.partition(key, value, self.partitions);
+
+        if partition >= self.partitions {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "illegal partition for {} ({})",
+                    String::from_utf8_lossy(key),
+                    partition
+                ),
+            ));
+        }
+
+        self.records.push(Record {
+            partition,
+            start,
+            key: start + key.len(),
+            value: end - value.len(),
+            end,
+        });
+
+        Ok(())
+    }
+
+    /// Returns the amount of memory used by buffered records.
+    #[inline]
+    fn size(&self) -> usize {
+        self.buffer.len() + self.records.len() * mem::size_of::<Record>()
+    }
+
+    /// Sorts all buffered records on the partition and key.
+    ///
+    /// This sort is stable, so values retain the order in which they
+    /// were written; this matches the behaviour of the Hadoop sort.
+    fn sort(&mut self) {
+        let buffer = &self.buffer;
+        let comparator = &self.comparator;
+        self.records.sort_by(|a, b| {
+            a.partition
+                .cmp(&b.partition)
+                .then_with(|| comparator.compare(&buffer[a.start..a.key], &buffer[b.start..b.key]))
+        });
+    }
+
+    /// Sorts and spills all buffered records to disk.
+    fn spill(&mut self) -> io::Result<()> {
+        self.sort();
+
+        let (spill, mut writer) = Spill::create(&self.dir)?;
+
+        for record in &self.records {
+            let (partition, key, value) = self.pair(record);
+            writer.write(partition, key, value)?;
+        }
+
+        writer.finish()?;
+
+        // keep only the bytes of any incomplete record
+        self.records.clear();
+        self.buffer.drain(..self.offset);
+        self.offset = 0;
+
+        self.spills.push(spill);
+
+        Ok(())
+    }
+}
+
+/// `Write` implementation to allow a `Shuffle` to act as stage output.
+impl Write for Shuffle {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // start searching from the new bytes
+        let mut start = self.buffer.len();
+
+        // append all bytes to the buffer
+        self.buffer.extend_from_slice(buf);
+
+        // store a record for each newline found in the new bytes
+        while let Some(n) = self.buffer[start..].iter().position(|b| *b == b'\n') {
+            let end = start + n;
+
+            self.push(self.offset, end)?;
+            self.offset = end + 1;
+
+            start = end + 1;
+        }
+
+        // spill to disk once the memory limit is reached
+        if !self.records.is_empty() && self.size() >= self.limit {
+            self.spill()?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Records structure to represent the sorted output of a `Shuffle`.
+pub(crate) struct Records {
+    cursor: Cursor,
+    entry: Vec<u8>,
+}
+
+/// Cursor enum to represent the position in a stream of records.
+enum Cursor {
+    Memory(Shuffle, usize),
+    Merge(Merge),
+}
+
+impl Records {
+    /// Feeds all records of a partition through a `Lifecycle`, in key order.
+    ///
+    /// Each record is joined back together using the input delimiter
+    /// found in the provided `Context`, to match the format expected
+    /// by the receiving stage.
+    pub(crate) fn feed<L>(
+        &mut self,
+        partition: usize,
+        lifecycle: &mut L,
+        ctx: &mut Context,
+    ) -> io::Result<()>
+    where
+        L: Lifecycle + ?Sized,
+    {
+        // grab the delimiter used to join the record back together
+        let delim = ctx.get::<Delimiters>().unwrap().input().to_vec();
+
+        loop {
+            // find the current record, if any
+            let (current, key, value) = match self.cursor.current() {
+                Some(record) => record,
+                None => return Ok(()),
+            };
+
+            // stop at the end of the partition
+            if current != partition {
+                return Ok(());
+            }
+
+            // join the record using the reusable buffer
+            self.entry.clear();
+            self.entry.extend_from_slice(key);
+            self.entry.extend_from_slice(&delim);
+            self.entry.extend_from_slice(value);
+
+            lifecycle.on_entry(&self.entry, ctx);
+
+            // failed stages receive no further records
+            if ctx.failed() {
+                return Ok(());
+            }
+
+            self.cursor.advance()?;
+        }
+    }
+}
+
+impl Cursor {
+    /// Returns the current record, if any.
+    #[inline]
+    fn current(&self) -> Option<(usize, &[u8], &[u8])> {
+        match self {
+            Cursor::Memory(shuffle, idx) => shuffle.records.get(*idx).map(|r| shuffle.pair(r)),
+            Cursor::Merge(merge) => merge.current(),
+        }
+    }
+
+    /// Advances to the next record.
+    #[inline]
+    fn advance(&mut self) -> io::Result<()> {
+        match self {
+            Cursor::Memory(_, idx) => {
+                *idx += 1;
+                Ok(())
+            }
+            Cursor::Merge(merge) => merge.advance(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::Contextual;
+    use crate::partition::HashPartitioner;
+
+    #[test]
+    fn test_shuffle_empty() {
+        let conf = Configuration::with_env(Vec::<(String, String)>::new().into_iter());
+        let shuffle = Shuffle::new(&conf, Rc::new(HashPartitioner), 1);
+
+        assert!(test_feed(conf, shuffle, 1).is_empty());
+    }
+
+    #[test]
+    fn test_shuffle_in_memory() {
+        let conf = Configuration::with_env(Vec::<(String, String)>::new().into_iter());
+        let mut shuffle = Shuffle::new(&conf, Rc::new(HashPartitioner), 1);
+
+        test_write(&mut shuffle);
+
+        assert!(shuffle.spills.is_empty());
+        assert_eq!(test_feed(conf, shuffle, 1), test_expected());
+    }
+
+    #[test]
+    fn test_shuffle_spilling() {
+        let env = vec![("mapreduce.task.io.sort.factor", "2")];
+        let conf = Configuration::with_env(env.into_iter());
+        let mut shuffle = Shuffle::new(&conf, Rc::new(HashPartitioner), 1);
+
+        shuffle.limit = 1;
+
+        test_write(&mut shuffle);
+
+        assert_eq!(shuffle.spills.len(), 3);
+        assert_eq!(test_feed(conf, shuffle, 1), test_expected());
+    }
+
+    #[test]
+    fn test_shuffle_partitioning() {
+        let conf = Configuration::with_env(Vec::<(String, String)>::new().into_iter());
+        let partitioner = |key: &[u8], _: &[u8], _: usize| (key[0] == b's') as usize;
+        let mut shuffle = Shuffle::new(&conf, Rc::new(partitioner), 2);
+
+        test_write(&mut shuffle);
+
+        assert_eq!(
+            test_feed(conf, shuffle, 2),
+            vec![
+                &b"0:first\tone"[..],
+                b"0:first\ttwo",
+                b"0:third\t",
+                b"1:second\tone",
+                b"1:second\ttwo",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shuffle_comparator() {
+        let env = vec![
+            ("mapreduce.task.io.sort.factor", "2"),
+            ("mapreduce.partition.keycomparator.options", "-k1,1r"),
+            (
+                "mapreduce.job.output.key.comparator.class",
+                "org.apache.hadoop.mapreduce.lib.partition.KeyFieldBasedComparator",
+            ),
+        ];
+        let conf = Configuration::with_env(env.into_iter());
+
+        for limit in &[usize::MAX, 1] {
+            let mut shuffle = Shuffle::new(&conf, Rc::new(HashPartitioner), 1);
+
+            shuffle.limit = *limit;
+
+            test_write(&mut shuffle);
+
+            assert_eq!(
+                test_feed(conf.clone(), shuffle, 1),
+                vec![
+                    &b"0:third\t"[..],
+                    b"0:second\tone",
+                    b"0:second\ttwo",
+                    b"0:first\tone",
+                    b"0:first\ttwo",
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn test_shuffle_illegal_partition() {
+        let conf = Configuration::with_env(Vec::<(String, String)>::new().into_iter());
+        let partitioner = |_: &[u8], _: &[u8], partitions: usize| partitions;
+        let mut shuffle = Shuffle::new(&conf, Rc::new(partitioner), 2);
+
+        let err = shuffle.write_all(b"first\tone\n").unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(err.to_string(), "illegal partition for first (2)");
+    }
+
+    fn test_write(shuffle: &mut Shuffle) {
+        shuffle.write_all(b"second\tone\nfirst\tone\n").unwrap();
+        shuffle.write_all(b"second\ttwo\nfir").unwrap();
+        shuffle.write_all(b"st\ttwo\nthird").unwrap();
+    }
+
+    fn test_feed(conf: Configuration, shuffle: Shuffle, partitions: usize) -> Vec<Vec<u8>> {
+        let mut ctx = Context::with_config(conf, io::sink());
+        let mut lifecycle = TestLifecycle;
+        let mut records = shuffle.into_records().unwrap();
+
+        ctx.insert(TestEntries(Vec::new()));
+
+        for partition in 0..partitions {
+            ctx.insert(TestPartition(partition));
+            records.feed(partition, &mut lifecycle, &mut ctx).unwrap();
+        }
+
+        ctx.take::<TestEntries>().unwrap().0
+    }
+
+    fn test_expected() -> Vec<&'static [u8]> {
+        vec![
+            b"0:first\tone",
+            b"0:first\ttwo",
+            b"0:second\tone",
+            b"0:second\ttwo",
+            b"0:third\t",
+        ]
+    }
+
+    struct TestEntries(Vec<Vec<u8>>);
+    struct TestPartition(usize);
+    struct TestLifecycle;
+
+    impl Contextual for TestEntries {}
+    impl Contextual for TestPartition {}
+
+    impl Lifecycle for TestLifecycle {
+        fn on_entry(&mut self, input: &[u8], ctx: &mut Context) {
+            let partition = ctx.get::<TestPartition>().unwrap().0;
+            let mut entry = format!("{}:", partition).into_bytes();
+
+            entry.extend_from_slice(input);
+
+            ctx.get_mut::<TestEntries>().unwrap().0.push(entry);
+        }
+    }
+}
@@ -0,0 +1,355 @@
+//! Spill bindings to store sorted runs of records on disk.
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+use crate::keys::KeyFields;
+
+/// Global counter used to generate unique spill file names.
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Spill structure to represent a sorted run of records on disk.
+///
+/// Records are stored as a partition followed by a pair of length
+/// prefixed key and value, so any bytes are safe to store. The file
+/// is removed as soon as the `Spill` is dropped.
+#[derive(Debug)]
+pub(crate) struct Spill {
+    path: PathBuf,
+}
+
+impl Spill {
+    /// Creates a new `Spill` inside the provided directory.
+    pub(crate) fn create(dir: &Path) -> io::Result<(Spill, SpillWriter)> {
+        // generate a unique name for the spill file
+        let name = format!(
+            "efflux-{}-{}.spill",
+            process::id(),
+            COUNTER.fetch_add(1, AtomicOrdering::Relaxed)
+        );
+
+        // create the file, failing on any existing file
+        let path = dir.join(name);
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+
+        let writer = SpillWriter {
+            inner: BufWriter::new(file),
+        };
+
+        Ok((Spill { path }, writer))
+    }
+
+    /// Opens a reader over all records stored in this `Spill`.
+    fn reader(&self) -> io::Result<SpillReader> {
+        Ok(SpillReader {
+            inner: BufReader::new(File::open(&self.path)?),
+        })
+    }
+}
+
+/// Removes the underlying spill file on drop.
+impl Drop for Spill {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Writer structure to append records to a `Spill`.
+pub(crate) struct SpillWriter {
+    inner: BufWriter<File>,
+}
+
+impl SpillWriter {
+    /// Appends a key/value pair to the spill.
+    pub(crate) fn write(&mut self, partition: usize, key: &[u8], value: &[u8]) -> io::Result<()> {
+        self.inner.write_all(&(partition as u32).to_be_bytes())?;
+        self.inner.write_all(&(key.len() as u32).to_be_bytes())?;
+        self.inner.write_all(key)?;
+        self.inner.write_all(&(value.len() as u32).to_be_bytes())?;
+        self.inner.write_all(value)
+    }
+
+    /// Finalizes the spill by flushing all buffered records.
+    pub(crate) fn finish(mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reader structure to iterate records stored in a `Spill`.
+struct SpillReader {
+    inner: BufReader<File>,
+}
+
+impl SpillReader {
+    /// Reads the next record into the provided head.
+    ///
+    /// If the end of the spill has been reached, `false` is returned.
+    fn next(&mut self, head: &mut Head) -> io::Result<bool> {
+        let mut len = [0; 4];
+
+        // check for the end of the spill
+        match self.inner.read_exact(&mut len) {
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+            Err(e) => return Err(e),
+            Ok(_) => (),
+        }
+
+        // read the partition of the record
+        head.partition = u32::from_be_bytes(len) as usize;
+
+        let key = &mut head.key;
+        let value = &mut head.value;
+
+        // read the key into the buffer
+        self.inner.read_exact(&mut len)?;
+        key.resize(u32::from_be_bytes(len) as usize, 0);
+        self.inner.read_exact(key)?;
+
+        // read the value into the buffer
+        self.inner.read_exact(&mut len)?;
+        value.resize(u32::from_be_bytes(len) as usize, 0);
+        self.inner.read_exact(value)?;
+
+        Ok(true)
+    }
+}
+
+/// Merge structure to combine a set of spills into a single sorted stream.
+///
+/// If there are more spills than the provided merge factor, spills are
+/// merged in several passes (as is done by Hadoop) to avoid having too
+/// many files open at once. Records with equal keys are emitted in the
+/// order of the spills they belong to, keeping the merge stable.
+pub(crate) struct Merge {
+    spills: Vec<Spill>,
+    readers: Vec<SpillReader>,
+    heap: BinaryHeap<Head>,
+    current: Option<Head>,
+}
+
+impl Merge {
+    /// Creates a new `Merge` across a set of spills.
+    ///
+    /// The merge is positioned on the first record of the stream.
+    pub(crate) fn new(
+        mut spills: Vec<Spill>,
+        dir: &Path,
+        factor: usize,
+        comparator: Rc<KeyFields>,
+    ) -> io::Result<Self> {
+        // always merge at least two spills at once
+        let factor = factor.max(2);
+
+        // merge intermediate passes until a single pass is possible
+        while spills.len() > factor {
+            let mut merged = Vec::with_capacity(spills.len() / factor + 1);
+            let mut chunks = Vec::new();
+
+            // group the spills into chunks of the merge factor
+            while !spills.is_empty() {
+                let rest = spills.split_off(factor.min(spills.len()));
+                chunks.push(spills);
+                spills = rest;
+            }
+
+            for chunk in chunks {
+                let (spill, mut writer) = Spill::create(dir)?;
+                let mut merge = Merge::new(chunk, dir, factor, comparator.clone())?;
+
+                while let Some((partition, key, value)) = merge.current() {
+                    writer.write(partition, key, value)?;
+                    merge.advance()?;
+                }
+
+                writer.finish()?;
+                merged.push(spill);
+            }
+
+            spills = merged;
+        }
+
+        // open a reader against every spill
+        let mut readers = spills
+            .iter()
+            .map(Spill::reader)
+            .collect::<io::Result<Vec<_>>>()?;
+
+        // seed the heap with the first record of every spill
+        let mut heap = BinaryHeap::with_capacity(readers.len());
+        for (run, reader) in readers.iter_mut().enumerate() {
+            let mut head = Head {
+                run,
+                partition: 0,
+                key: Vec::new(),
+                value: Vec::new(),
+                comparator: comparator.clone(),
+            };
+
+            if reader.next(&mut head)? {
+                heap.push(head);
+            }
+        }
+
+        // position on the lowest record
+        let current = heap.pop();
+
+        Ok(Self {
+            spills,
+            readers,
+            heap,
+            current,
+        })
+    }
+
+    /// Returns the current record of the merge, if any.
+    #[inline]
+    pub(crate) fn current(&self) -> Option<(usize, &[u8], &[u8])> {
+        self.current
+            .as_ref()
+            .map(|head| (head.partition, &head.key[..], &head.value[..]))
+    }
+
+    /// Advances the merge to the next record.
+    pub(crate) fn advance(&mut self) -> io::Result<()> {
+        // replace the current record from the same spill
+        if let Some(mut head) = self.current.take() {
+            if self.readers[head.run].next(&mut head)? {
+                self.heap.push(head);
+            }
+        }
+
+        // the lowest record is the next
+        self.current = self.heap.pop();
+
+        // drop spills once the merge has finished
+        if self.current.is_none() {
+            self.readers.clear();
+            self.spills.clear();
+        }
+
+        Ok(())
+    }
+}
+
+/// Head structure to represent the current record of a spill in a merge.
+struct Head {
+    run: usize,
+    partition: usize,
+    key: Vec<u8>,
+    value: Vec<u8>,
+    comparator: Rc<KeyFields>,
+}
+
+/// Ordering is reversed to turn the `BinaryHeap` into a min-heap, with
+/// ties on the partition and key being broken by the spill position.
+impl Ord for Head {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .partition
+            .cmp(&self.partition)
+            .then_with(|| self.comparator.compare(&other.key, &self.key))
+            .then_with(|| other.run.cmp(&self.run))
+    }
+}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_spill_round_trip() {
+        let dir = env::temp_dir();
+        let (spill, mut writer) = Spill::create(&dir).unwrap();
+
+        writer.write(0, b"key", b"value").unwrap();
+        writer.write(3, b"k\ney", b"").unwrap();
+        writer.finish().unwrap();
+
+        let mut reader = spill.reader().unwrap();
+        let mut head = Head {
+            run: 0,
+            partition: 0,
+            key: Vec::new(),
+            value: Vec::new(),
+            comparator: Rc::new(KeyFields::parse("", b"\t")),
+        };
+
+        assert!(reader.next(&mut head).unwrap());
+        assert_eq!(head.partition, 0);
+        assert_eq!(
+            (&head.key[..], &head.value[..]),
+            (&b"key"[..], &b"value"[..])
+        );
+
+        assert!(reader.next(&mut head).unwrap());
+        assert_eq!(head.partition, 3);
+        assert_eq!((&head.key[..], &head.value[..]), (&b"k\ney"[..], &b""[..]));
+
+        assert!(!reader.next(&mut head).unwrap());
+
+        let path = spill.path.clone();
+
+        assert!(path.exists());
+        drop(spill);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_spill_merging() {
+        let dir = env::temp_dir();
+        let mut spills = Vec::new();
+
+        for run in &[&["a", "c", "e"][..], &["b", "c"], &["a", "d"], &["c"]] {
+            let (spill, mut writer) = Spill::create(&dir).unwrap();
+            for key in run.iter() {
+                let partition = if *key == "e" { 2 } else { 1 };
+                let value = [b'0' + spills.len() as u8];
+                writer.write(partition, key.as_bytes(), &value).unwrap();
+            }
+            writer.finish().unwrap();
+            spills.push(spill);
+        }
+
+        let comparator = Rc::new(KeyFields::parse("", b"\t"));
+        let mut merge = Merge::new(spills, &dir, 2, comparator).unwrap();
+        let mut merged = Vec::new();
+
+        while let Some((partition, key, value)) = merge.current() {
+            merged.push(format!(
+                "{}{}{}",
+                partition,
+                String::from_utf8_lossy(key),
+                String::from_utf8_lossy(value)
+            ));
+            merge.advance().unwrap();
+        }
+
+        assert_eq!(
+            merged,
+            vec!["1a0", "1a2", "1b1", "1c0", "1c1", "1c3", "1d2", "2e0"]
+        );
+    }
+}
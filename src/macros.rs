@@ -20,6 +20,8 @@ macro_rules! log {
 ///
 /// This is simply a sane wrapper around `log!` to ensure that
 /// counter updates are always logged in the correct formatting.
+/// Each update is sent immediately; when counting per record, the
+/// aggregated `Context::counter` API should be preferred.
 #[macro_export]
 macro_rules! update_counter {
     ($group:expr, $label:expr, $amount:expr) => {
@@ -3,8 +3,8 @@
 //! This module offers the `Mapper` trait, which allows a developer
 //! to easily create a mapping stage due to the sane defaults. Also
 //! offered is the `MapperLifecycle` binding for use as an IO stage.
-use crate::context::{Context, Offset};
-use crate::io::Lifecycle;
+use crate::context::{Context, InputSplit, Offset, TaskInfo};
+use crate::io::{Input, Lifecycle};
 
 /// Trait to represent the mapping stage of MapReduce.
 ///
@@ -65,26 +65,63 @@ where
     M: Mapper,
 {
     /// Creates all required state for the lifecycle.
+    ///
+    /// The byte offset starts from the beginning of the `InputSplit`,
+    /// so that keys match the position of each entry in the input file.
     #[inline]
     fn on_start(&mut self, ctx: &mut Context) {
-        ctx.insert(Offset::new());
+        let start = ctx.get::<InputSplit>().map_or(0, InputSplit::start);
+
+        ctx.insert(Offset::with_start(start));
+        ctx.get_mut::<TaskInfo>().unwrap().stage(true);
         self.mapper.setup(ctx);
     }
 
-    /// Passes each entry through to the mapper as a value, with the current
-    /// byte offset being provided as the key (this follows the implementation
-    /// provided in the Hadoop MapReduce Java interfaces, but it's unclear as
-    /// to whether this is the desired default behaviour here).
+    /// Passes each entry through to the mapper as a value, with the byte
+    /// offset of the entry being provided as the key (this follows the
+    /// `TextInputFormat` implementation provided by Hadoop MapReduce).
+    ///
+    /// Entries passed directly are assumed to end with a single `\n`, as
+    /// the real line terminator is only known when reading the input.
     #[inline]
     fn on_entry(&mut self, input: &[u8], ctx: &mut Context) {
         let offset = {
-            // grabs the offset from the context, and shifts the offset
-            ctx.get_mut::<Offset>().unwrap().shift(input.len() + 2)
+            // grabs the offset from the context, and shifts past the entry
+            let offset = ctx.get_mut::<Offset>().unwrap();
+            let current = offset.get();
+            offset.shift(input.len() + 1);
+            current
         };
 
         self.mapper.map(offset, input, ctx);
     }
 
+    /// Passes each entry of the input through to the mapper, tracking the
+    /// byte offsets using the real position of each entry in the input.
+    fn on_input(&mut self, input: &mut Input, ctx: &mut Context) {
+        // offsets within the input are relative to the current offset
+        let base = ctx.get::<Offset>().map_or(0, Offset::get);
+
+        while !ctx.failed() {
+            let done = match input.next_with_offset() {
+                Some((offset, entry)) => {
+                    self.mapper.map(base + offset, entry, ctx);
+                    false
+                }
+                None => true,
+            };
+
+            // move the offset past everything consumed so far
+            let offset = ctx.get_mut::<Offset>().unwrap();
+            let current = offset.get();
+            offset.shift(base + input.position() - current);
+
+            if done {
+                break;
+            }
+        }
+    }
+
     /// Finalizes the lifecycle by calling cleanup.
     #[inline]
     fn on_end(&mut self, ctx: &mut Context) {
@@ -96,6 +133,7 @@ where
 mod tests {
     use super::*;
     use crate::context::Contextual;
+    use crate::io::typedbytes::Value;
     use crate::io::Lifecycle;
 
     #[test]
@@ -119,14 +157,149 @@ mod tests {
                 assert_eq!(pair.1, input);
             };
 
-            vet(b"first_input_line", 18);
-            vet(b"second_input_line", 37);
-            vet(b"third_input_line", 55);
+            vet(b"first_input_line", 0);
+            vet(b"second_input_line", 17);
+            vet(b"third_input_line", 35);
         }
 
         mapper.on_end(&mut ctx);
     }
 
+    #[test]
+    fn test_mapper_split_offset() {
+        let env = vec![
+            ("mapreduce.task.ismap", "true"),
+            ("mapreduce.map.input.start", "100"),
+        ];
+        let conf = crate::context::Configuration::with_env(env.into_iter());
+
+        let mut ctx = Context::with_config(conf, std::io::sink());
+        let mut mapper = MapperLifecycle::new(TestMapper);
+
+        mapper.on_start(&mut ctx);
+        mapper.on_entry(b"first_input_line", &mut ctx);
+
+        assert_eq!(ctx.get::<TestPair>().unwrap().0, 100);
+    }
+
+    #[test]
+    fn test_mapper_line_offsets() {
+        let input = b"one\ntwo\r\nthree\n\nfour";
+        let mut output = Vec::new();
+
+        crate::run_mapper_with(
+            &input[..],
+            &mut output,
+            |key, value: &[u8], ctx: &mut Context| {
+                ctx.write_fmt(key, String::from_utf8_lossy(value));
+            },
+        )
+        .unwrap();
+
+        assert_eq!(output, b"0\tone\n4\ttwo\n9\tthree\n15\t\n16\tfour\n");
+    }
+
+    #[test]
+    fn test_mapper_record_delimiter() {
+        let env = vec![
+            ("mapreduce.task.ismap", "true"),
+            ("textinputformat.record.delimiter", "\u{1e}"),
+        ];
+        let conf = crate::context::Configuration::with_env(env.into_iter());
+
+        let input = b"one\ntwo\x1ethree\x1e";
+        let mut output = Vec::new();
+
+        {
+            let mut ctx = Context::with_config(conf, &mut output);
+            let mut mapper = MapperLifecycle::new(|key, value: &[u8], ctx: &mut Context| {
+                ctx.write_fmt(key, String::from_utf8_lossy(value).replace('\n', " "));
+            });
+
+            mapper.on_start(&mut ctx);
+            crate::io::run_entries(&input[..], &mut mapper, &mut ctx).unwrap();
+            mapper.on_end(&mut ctx);
+        }
+
+        assert_eq!(output, b"0\tone two\n8\tthree\n");
+    }
+
+    #[test]
+    fn test_mapper_xml_fragments() {
+        let env = vec![
+            ("mapreduce.task.ismap", "true"),
+            ("mapreduce.map.input.start", "10"),
+            ("stream.recordreader.begin", "<page>"),
+            ("stream.recordreader.end", "</page>"),
+        ];
+        let conf = crate::context::Configuration::with_env(env.into_iter());
+
+        let input =
+            b"<xml>\n  <page>\n    <id>1</id>\n  </page>\n  <page><id>2</id></page>\n</xml>\n";
+        let mut output = Vec::new();
+
+        {
+            let mut ctx = Context::with_config(conf, &mut output);
+            let mut mapper = MapperLifecycle::new(|key, value: &[u8], ctx: &mut Context| {
+                ctx.write_fmt(key, value.iter().filter(|b| **b == b'\n').count());
+            });
+
+            mapper.on_start(&mut ctx);
+            crate::io::run_entries(&input[..], &mut mapper, &mut ctx).unwrap();
+
+            assert_eq!(ctx.get::<Offset>().unwrap().get(), 10 + 73);
+
+            mapper.on_end(&mut ctx);
+        }
+
+        assert_eq!(output, b"18\t2\n52\t0\n");
+    }
+
+    #[test]
+    fn test_mapper_custom_io() {
+        let input = b"first_input_line\nsecond_input_line\n";
+        let mut output = Vec::new();
+
+        crate::run_mapper_with(
+            &input[..],
+            &mut output,
+            |_key, value: &[u8], ctx: &mut Context| {
+                ctx.write(value, b"1");
+            },
+        )
+        .unwrap();
+
+        assert_eq!(output, b"first_input_line\t1\nsecond_input_line\t1\n");
+    }
+
+    #[test]
+    fn test_mapper_typed_bytes() {
+        let env = vec![
+            ("mapreduce.task.ismap", "true"),
+            ("stream.map.input", "typedbytes"),
+        ];
+        let conf = crate::context::Configuration::with_env(env.into_iter());
+
+        let mut input = Value::from("one\ttwo").encode();
+        input.extend(Value::Long(3).encode());
+
+        let mut output = Vec::new();
+
+        {
+            let mut ctx = Context::with_config(conf, &mut output);
+            let mut mapper = MapperLifecycle::new(|_key, value: &[u8], ctx: &mut Context| {
+                let value = Value::decode(value).unwrap();
+                ctx.write_fmt(format!("{:?}", value), 1);
+            });
+
+            mapper.on_start(&mut ctx);
+            crate::io::run_entries(&input[..], &mut mapper, &mut ctx).unwrap();
+            mapper.on_end(&mut ctx);
+        }
+
+        assert_eq!(output, b"String(\"one\\ttwo\")\t1\nLong(3)\t1\n");
+    }
+
     struct TestPair(usize, Vec<u8>);
 
     impl Contextual for TestPair {}
@@ -0,0 +1,238 @@
+//! Partitioning of keys across the reduction stage.
+//!
+//! This module offers the `Partitioner` trait, which decides which reducer
+//! a key is sent to when a job runs with several reducers. Hadoop-compatible
+//! implementations of the `HashPartitioner` and `KeyFieldBasedPartitioner`
+//! are provided, so that keys end up in the same partitions as they would
+//! when running on a cluster.
+use crate::context::Configuration;
+use crate::keys::{self, KeyFields};
+
+/// Trait to represent the partitioning of keys between reducers.
+pub trait Partitioner {
+    /// Returns the partition of a key/value pair, in `0..partitions`.
+    fn partition(&self, key: &[u8], value: &[u8], partitions: usize) -> usize;
+}
+
+/// Enables raw functions to act as `Partitioner` types.
+impl<P> Partitioner for P
+where
+    P: Fn(&[u8], &[u8], usize) -> usize,
+{
+    /// Partitioning by passing through the pair to the inner closure.
+    #[inline]
+    fn partition(&self, key: &[u8], value: &[u8], partitions: usize) -> usize {
+        self(key, value, partitions)
+    }
+}
+
+/// Partitioner based on the hash of the entire key.
+///
+/// This matches the default `HashPartitioner` of Hadoop, using the hash
+/// of the `Text` type used to represent keys in Hadoop Streaming.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HashPartitioner;
+
+impl Partitioner for HashPartitioner {
+    /// Partitions a key using the Hadoop `Text` hash.
+    #[inline]
+    fn partition(&self, key: &[u8], _value: &[u8], partitions: usize) -> usize {
+        modulo(hash_bytes(1, key), partitions)
+    }
+}
+
+/// Partitioner based on the hash of selected fields of the key.
+///
+/// This matches the `KeyFieldBasedPartitioner` of Hadoop, and is configured
+/// using the `mapreduce.partition.keypartitioner.options` (or the older
+/// `num.key.fields.for.partition`) value of a job `Configuration`. Fields
+/// are separated by `mapreduce.map.output.key.field.separator`.
+#[derive(Clone, Debug)]
+pub struct KeyFieldBasedPartitioner {
+    keys: KeyFields,
+}
+
+impl KeyFieldBasedPartitioner {
+    /// Creates a new `KeyFieldBasedPartitioner` from a job `Configuration`.
+    pub fn new(conf: &Configuration) -> Self {
+        // fetch the separator used to split key fields
+        let separator = keys::separator(conf);
+
+        // the older setting takes priority, as it does in Hadoop
+        let keys = match conf
+            .get("num.key.fields.for.partition")
+            .and_then(|fields| fields.trim().parse::<usize>().ok())
+        {
+            Some(fields) => KeyFields::range(1, fields, separator),
+            None => KeyFields::parse(
+                conf.get("mapreduce.partition.keypartitioner.options")
+                    .unwrap_or(""),
+                separator,
+            ),
+        };
+
+        Self { keys }
+    }
+
+    /// Creates a new `KeyFieldBasedPartitioner` on a prefix of the key fields.
+    ///
+    /// This is the equivalent of configuring the options as `-k1,{fields}`,
+    /// and ensures all keys of a group reach the same reducer when grouping
+    /// on a prefix of the key fields.
+    pub fn prefix(conf: &Configuration, fields: usize) -> Self {
+        Self {
+            keys: KeyFields::range(1, fields, keys::separator(conf)),
+        }
+    }
+}
+
+impl Partitioner for KeyFieldBasedPartitioner {
+    /// Partitions a key using a hash of the selected key fields.
+    fn partition(&self, key: &[u8], _value: &[u8], partitions: usize) -> usize {
+        // no specs means the hash of a Java `String`
+        if self.keys.specs().is_empty() {
+            return modulo(hash_string(key), partitions);
+        }
+
+        // empty keys are always the first partition
+        if key.is_empty() {
+            return 0;
+        }
+
+        let fields = self.keys.fields(key);
+        let mut hash = 0;
+
+        // hash each selected portion of the key in turn
+        for spec in self.keys.specs() {
+            if let Some((start, end)) = self.keys.select(key, &fields, spec) {
+                hash = hash_bytes(hash, &key[start..end]);
+            }
+        }
+
+        modulo(hash, partitions)
+    }
+}
+
+/// Creates the `Partitioner` configured for a job.
+///
+/// This will be a `KeyFieldBasedPartitioner` when configured as the job
+/// partitioner via `mapreduce.job.partitioner.class`, and otherwise the
+/// default `HashPartitioner`.
+pub fn from_config(conf: &Configuration) -> Box<dyn Partitioner> {
+    if class(conf).ends_with("KeyFieldBasedPartitioner") {
+        Box::new(KeyFieldBasedPartitioner::new(conf))
+    } else {
+        Box::new(HashPartitioner)
+    }
+}
+
+/// Determines whether a `Partitioner` is configured for a job.
+pub(crate) fn is_configured(conf: &Configuration) -> bool {
+    !class(conf).is_empty()
+}
+
+/// Returns the class name of the configured job partitioner, if any.
+fn class(conf: &Configuration) -> &str {
+    conf.get("mapreduce.job.partitioner.class")
+        .or_else(|| conf.get("mapred.partitioner.class"))
+        .map(str::trim)
+        .unwrap_or("")
+}
+
+/// Hashes a slice of bytes as a Java `byte[]`, from a starting hash.
+#[inline]
+fn hash_bytes(start: i32, bytes: &[u8]) -> i32 {
+    bytes.iter().fold(start, |hash, byte| {
+        hash.wrapping_mul(31).wrapping_add(i32::from(*byte as i8))
+    })
+}
+
+/// Hashes a slice of bytes as a Java `String`.
+#[inline]
+fn hash_string(bytes: &[u8]) -> i32 {
+    String::from_utf8_lossy(bytes)
+        .encode_utf16()
+        .fold(0, |hash, unit| {
+            hash.wrapping_mul(31).wrapping_add(i32::from(unit))
+        })
+}
+
+/// Converts a hash into a partition, in the same way as Hadoop.
+#[inline]
+fn modulo(hash: i32, partitions: usize) -> usize {
+    (hash & i32::MAX) as usize % partitions.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_java_hashes() {
+        assert_eq!(hash_string(b"hello"), 99_162_322);
+        assert_eq!(hash_string(b"\xc3\xa9t\xc3\xa9"), 227_742);
+        assert_eq!(hash_string(b"polygenelubricants"), i32::MIN);
+        assert_eq!(hash_bytes(1, b"hello"), 127_791_473);
+        assert_eq!(hash_bytes(1, b"\xff"), 30);
+    }
+
+    #[test]
+    fn test_hash_partitioner() {
+        let partitioner = HashPartitioner;
+
+        assert_eq!(partitioner.partition(b"hello", b"", 10), 3);
+        assert_eq!(partitioner.partition(b"hello", b"", 1), 0);
+        assert_eq!(modulo(i32::MIN, 10), 0);
+    }
+
+    #[test]
+    fn test_key_field_partitioner() {
+        let env = vec![
+            ("mapreduce.partition.keypartitioner.options", "-k1,1"),
+            ("mapreduce.map.output.key.field.separator", "."),
+        ];
+
+        let conf = Configuration::with_env(env.into_iter());
+        let partitioner = KeyFieldBasedPartitioner::new(&conf);
+
+        let first = partitioner.partition(b"hello.one", b"", 7);
+        let second = partitioner.partition(b"hello.two", b"", 7);
+
+        assert_eq!(first, second);
+        assert_eq!(first, modulo(hash_bytes(0, b"hello"), 7));
+        assert_eq!(partitioner.partition(b"", b"", 7), 0);
+    }
+
+    #[test]
+    fn test_key_field_partitioner_prefix() {
+        let conf = Configuration::with_env(Vec::<(String, String)>::new().into_iter());
+        let partitioner = KeyFieldBasedPartitioner::prefix(&conf, 1);
+
+        let first = partitioner.partition(b"hello\t1", b"", 7);
+        let second = partitioner.partition(b"hello\t2", b"", 7);
+
+        assert_eq!(first, second);
+        assert_eq!(first, modulo(hash_bytes(0, b"hello"), 7));
+    }
+
+    #[test]
+    fn test_key_field_partitioner_defaults() {
+        let conf = Configuration::with_env(Vec::<(String, String)>::new().into_iter());
+        let partitioner = KeyFieldBasedPartitioner::new(&conf);
+
+        assert_eq!(partitioner.partition(b"hello", b"", 10), 2);
+    }
+
+    #[test]
+    fn test_partitioner_config() {
+        let env = vec![(
+            "mapreduce.job.partitioner.class",
+            "org.apache.hadoop.mapred.lib.KeyFieldBasedPartitioner",
+        )];
+
+        let conf = Configuration::with_env(env.into_iter());
+        let partitioner = from_config(&conf);
+
+        assert_eq!(partitioner.partition(b"hello", b"", 10), 2);
+    }
+}
@@ -3,8 +3,15 @@
 //! This module offers the `Reducer` trait, which allows a developer
 //! to easily create a reduction stage due to the sane defaults. Also
 //! offered is the `ReducerLifecycle` binding for use as an IO stage.
-use crate::context::{Context, Delimiters};
-use crate::io::Lifecycle;
+//!
+//! As a `Reducer` receives all values of a key at once, every group is
+//! buffered in memory. When groups can be too large for this, there is
+//! also the `StreamReducer` trait, which receives a lazy `Values` stream
+//! read directly from the input, at the cost of random access.
+use std::mem;
+
+use crate::context::{Context, Delimiters, TaskInfo};
+use crate::io::{Input, Lifecycle};
 
 /// Trait to represent the reduction stage of MapReduce.
 ///
@@ -42,6 +49,96 @@ where
     }
 }
 
+/// Trait to represent a streaming reduction stage of MapReduce.
+///
+/// This differs from the `Reducer` trait in that values are provided as
+/// a lazy `Values` stream, rather than being buffered up front. Memory
+/// usage is therefore constant, no matter how many values a key has.
+pub trait StreamReducer {
+    /// Setup handler for the current `StreamReducer`.
+    fn setup(&mut self, _ctx: &mut Context) {}
+
+    /// Reduction handler for the current `StreamReducer`.
+    ///
+    /// The default implementation of this handler will emit each value against
+    /// the key in the order they were received. Any values which are not read
+    /// by this handler are skipped once the handler returns.
+    fn reduce(&mut self, key: &[u8], values: &mut Values, ctx: &mut Context) {
+        while let Some(value) = values.next() {
+            ctx.write(key, value);
+        }
+    }
+
+    /// Cleanup handler for the current `StreamReducer`.
+    fn cleanup(&mut self, _ctx: &mut Context) {}
+}
+
+/// Enables raw functions to act as `StreamReducer` types.
+impl<R> StreamReducer for R
+where
+    R: FnMut(&[u8], &mut Values, &mut Context),
+{
+    /// Reduction handler by passing through the values to the inner closure.
+    #[inline]
+    fn reduce(&mut self, key: &[u8], values: &mut Values, ctx: &mut Context) {
+        self(key, values, ctx)
+    }
+}
+
+/// Values structure to represent the values of a key as a lazy stream.
+///
+/// Values are pulled from the stage input as they're requested, and so
+/// each value is only valid until the next value is read. Once all the
+/// values of the current key have been read, `None` is returned.
+pub struct Values<'v, 'i> {
+    input: &'v mut Input<'i>,
+    key: &'v [u8],
+    first: Option<&'v [u8]>,
+    next: &'v mut (Vec<u8>, Vec<u8>),
+    pending: bool,
+    done: bool,
+}
+
+impl Values<'_, '_> {
+    /// Retrieves the next value for the current key, if any.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<&[u8]> {
+        // the first value was read to detect the key
+        if let Some(first) = self.first.take() {
+            return Some(first);
+        }
+
+        // group has been exhausted
+        if self.done {
+            return None;
+        }
+
+        // no more input means no more values
+        let (key, value) = match self.input.next_pair() {
+            Some(pair) => pair,
+            None => {
+                self.done = true;
+                return None;
+            }
+        };
+
+        // same key means another value
+        if key == self.key {
+            return Some(value);
+        }
+
+        // otherwise store the pair to start the next group
+        self.next.0.clear();
+        self.next.0.extend_from_slice(key);
+        self.next.1.clear();
+        self.next.1.extend_from_slice(value);
+        self.pending = true;
+        self.done = true;
+
+        None
+    }
+}
+
 /// Lifecycle structure to represent a reduction.
 pub(crate) struct ReducerLifecycle<R>
 where
@@ -67,39 +164,11 @@ where
             values: Vec::new(),
         }
     }
-}
-
-/// `Lifecycle` implementation for the reduction stage.
-impl<R> Lifecycle for ReducerLifecycle<R>
-where
-    R: Reducer,
-{
-    /// Creates all required state for the lifecycle.
-    #[inline]
-    fn on_start(&mut self, ctx: &mut Context) {
-        self.reducer.setup(ctx);
-    }
 
-    /// Processes each entry by buffering sequential key entries into the
+    /// Processes each pair by buffering sequential key pairs into the
     /// internal group. Once the key changes the prior group is passed off
     /// into the actual `Reducer` trait, and the group is reset.
-    fn on_entry(&mut self, input: &[u8], ctx: &mut Context) {
-        let (key, value) = {
-            // grab the delimiters from the context
-            let delim = ctx.get::<Delimiters>().unwrap();
-
-            // search (quickly) for the input byte delimiter
-            match twoway::find_bytes(&input, delim.input()) {
-                Some(n) if n < input.len() => {
-                    // split the input at the given index when applicable
-                    (&input[..n], &input[n + delim.input().len()..])
-                }
-
-                // otherwise the input is the key
-                _ => (&input[..], &b""[..]),
-            }
-        };
-
+    pub(crate) fn on_pair(&mut self, key: &[u8], value: &[u8], ctx: &mut Context) {
         // first key
         if !self.on {
             self.on = true;
@@ -130,18 +199,141 @@ where
         self.values.clear();
         self.values.push(value.to_vec());
     }
+}
+
+/// `Lifecycle` implementation for the reduction stage.
+impl<R> Lifecycle for ReducerLifecycle<R>
+where
+    R: Reducer,
+{
+    /// Creates all required state for the lifecycle.
+    #[inline]
+    fn on_start(&mut self, ctx: &mut Context) {
+        ctx.get_mut::<TaskInfo>().unwrap().stage(false);
+        self.reducer.setup(ctx);
+    }
+
+    /// Processes each entry by splitting it into a pair using the
+    /// delimiters from the context, before grouping the pair.
+    fn on_entry(&mut self, input: &[u8], ctx: &mut Context) {
+        let (key, value) = ctx.get::<Delimiters>().unwrap().split_input(input);
+        self.on_pair(key, value, ctx);
+    }
+
+    /// Processes all input by pulling pairs directly from the input, as
+    /// this avoids splitting entries for binary input formats.
+    fn on_input(&mut self, input: &mut Input, ctx: &mut Context) {
+        while !ctx.failed() {
+            match input.next_pair() {
+                Some((key, value)) => self.on_pair(key, value, ctx),
+                None => break,
+            }
+        }
+    }
 
     /// Finalizes the lifecycle by emitting any leftover pairs.
     #[inline]
     fn on_end(&mut self, ctx: &mut Context) {
-        // construct a references list to avoid exposing vecs
-        let mut values = Vec::with_capacity(self.values.len());
-        for value in &self.values {
-            values.push(value.as_slice());
+        // only reduce when there was input
+        if self.on {
+            // construct a references list to avoid exposing vecs
+            let mut values = Vec::with_capacity(self.values.len());
+            for value in &self.values {
+                values.push(value.as_slice());
+            }
+
+            // reduce the last batche of values
+            self.reducer.reduce(&self.key, &values, ctx);
+
+            // reset for any further input
+            self.on = false;
+            self.values.clear();
         }
 
-        // reduce the last batche of values
-        self.reducer.reduce(&self.key, &values, ctx);
+        self.reducer.cleanup(ctx);
+    }
+}
+
+/// Lifecycle structure to represent a streaming reduction.
+pub(crate) struct StreamReducerLifecycle<R>
+where
+    R: StreamReducer,
+{
+    reducer: R,
+}
+
+/// Basic creation for `StreamReducerLifecycle`
+impl<R> StreamReducerLifecycle<R>
+where
+    R: StreamReducer,
+{
+    /// Constructs a new `StreamReducerLifecycle` instance.
+    pub(crate) fn new(reducer: R) -> Self {
+        Self { reducer }
+    }
+}
+
+/// `Lifecycle` implementation for the streaming reduction stage.
+impl<R> Lifecycle for StreamReducerLifecycle<R>
+where
+    R: StreamReducer,
+{
+    /// Creates all required state for the lifecycle.
+    #[inline]
+    fn on_start(&mut self, ctx: &mut Context) {
+        ctx.get_mut::<TaskInfo>().unwrap().stage(false);
+        self.reducer.setup(ctx);
+    }
+
+    /// Processes each group of sequential key entries by pulling values
+    /// from the input as the `StreamReducer` requests them. Values which
+    /// are not read by the `StreamReducer` are skipped afterwards.
+    fn on_input(&mut self, input: &mut Input, ctx: &mut Context) {
+        // buffers for the current group
+        let mut key = Vec::new();
+        let mut first = Vec::new();
+
+        // buffer for the first pair of each group
+        let mut next = match input.next_pair() {
+            Some((key, value)) => (key.to_vec(), value.to_vec()),
+            None => return,
+        };
+
+        loop {
+            // the first pair provides the group key and value
+            mem::swap(&mut key, &mut next.0);
+            mem::swap(&mut first, &mut next.1);
+
+            let mut values = Values {
+                input,
+                key: &key,
+                first: Some(&first),
+                next: &mut next,
+                pending: false,
+                done: false,
+            };
+
+            // reduce the key and value stream
+            self.reducer.reduce(&key, &mut values, ctx);
+
+            // no more input is read after failure
+            if ctx.failed() {
+                break;
+            }
+
+            // skip any values left unread
+            while values.next().is_some() {}
+
+            // no next entry means the input is done
+            if !values.pending {
+                break;
+            }
+        }
+    }
+
+    /// Finalizes the lifecycle by calling cleanup.
+    #[inline]
+    fn on_end(&mut self, ctx: &mut Context) {
         self.reducer.cleanup(ctx);
     }
 }
@@ -150,6 +342,7 @@ where
 mod tests {
     use super::*;
     use crate::context::Contextual;
+    use crate::io::typedbytes::Value;
     use crate::io::Lifecycle;
 
     #[test]
@@ -189,6 +382,23 @@ mod tests {
         assert_eq!(pair.1, vec![&b"one"[..], b"two", b"three"]);
     }
 
+    #[test]
+    fn test_reducer_custom_io() {
+        let input = b"first\tone\nfirst\ttwo\nsecond\tthree\n";
+        let mut output = Vec::new();
+
+        crate::run_reducer_with(
+            &input[..],
+            &mut output,
+            |key: &[u8], values: &[&[u8]], ctx: &mut Context| {
+                ctx.write(key, values.join(&b","[..]).as_slice());
+            },
+        )
+        .unwrap();
+
+        assert_eq!(output, b"first\tone,two\nsecond\tthree\n");
+    }
+
     #[test]
     fn test_reducer_empty_values() {
         let mut ctx = Context::new();
@@ -209,7 +419,170 @@ mod tests {
         assert_eq!(pair.1, vec![b"", b""]);
     }
 
+    #[test]
+    fn test_reducer_composite_keys() {
+        let env = vec![("stream.num.map.output.key.fields", "2")];
+        let conf = crate::context::Configuration::with_env(env.into_iter());
+
+        let mut ctx = Context::with_config(conf, std::io::sink());
+        let mut reducer = ReducerLifecycle::new(TestReducer);
+
+        reducer.on_start(&mut ctx);
+        reducer.on_entry(b"user\t1\tone", &mut ctx);
+        reducer.on_entry(b"user\t1\ttwo", &mut ctx);
+        reducer.on_entry(b"user\t2\tthree", &mut ctx);
+
+        let pair = ctx.get::<TestPair>().unwrap();
+
+        assert_eq!(pair.0, b"user\t1");
+        assert_eq!(pair.1, vec![&b"one"[..], b"two"]);
+
+        reducer.on_end(&mut ctx);
+
+        let pair = ctx.get::<TestPair>().unwrap();
+
+        assert_eq!(pair.0, b"user\t2");
+        assert_eq!(pair.1, vec![&b"three"[..]]);
+    }
+
+    #[test]
+    fn test_reducer_empty_input() {
+        let mut ctx = Context::new();
+        let mut reducer = ReducerLifecycle::new(TestReducer);
+
+        reducer.on_start(&mut ctx);
+        reducer.on_end(&mut ctx);
+
+        assert!(ctx.get::<TestPair>().is_none());
+    }
+
+    #[test]
+    fn test_stream_reducer_lifecycle() {
+        let input = b"first\tone\nfirst\ttwo\nsecond\t\nthird\tone\nthird\ttwo\n";
+        let mut output = Vec::new();
+
+        {
+            let mut ctx = Context::with_output(&mut output);
+            let mut reducer = StreamReducerLifecycle::new(TestStreamReducer);
+
+            reducer.on_start(&mut ctx);
+            crate::io::run_entries(&input[..], &mut reducer, &mut ctx).unwrap();
+            reducer.on_end(&mut ctx);
+        }
+
+        assert_eq!(output, b"first\tone,two\nsecond\t\nthird\tone,two\n");
+    }
+
+    #[test]
+    fn test_stream_reducer_partial_reads() {
+        let input = b"first\tone\nfirst\ttwo\nsecond\tone\nsecond\ttwo\n";
+        let mut output = Vec::new();
+
+        crate::run_stream_reducer_with(
+            &input[..],
+            &mut output,
+            |key: &[u8], values: &mut Values, ctx: &mut Context| {
+                ctx.write(key, values.next().unwrap());
+            },
+        )
+        .unwrap();
+
+        assert_eq!(output, b"first\tone\nsecond\tone\n");
+    }
+
+    #[test]
+    fn test_stream_reducer_empty_input() {
+        let mut output = Vec::new();
+
+        crate::run_stream_reducer_with(&b""[..], &mut output, TestStreamReducer).unwrap();
+
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_reducer_typed_bytes() {
+        let env = vec![
+            ("stream.reduce.input", "typedbytes"),
+            ("stream.reduce.output", "typedbytes"),
+        ];
+        let conf = crate::context::Configuration::with_env(env.into_iter());
+
+        let mut input = Vec::new();
+        for (key, value) in &[("a\tb", 1), ("a\tb", 2), ("c\n", 3)] {
+            input.extend(Value::from(*key).encode());
+            input.extend(Value::Int(*value).encode());
+        }
+
+        let mut output = Vec::new();
+
+        {
+            let mut ctx = Context::with_config(conf, &mut output);
+            let mut reducer =
+                ReducerLifecycle::new(|key: &[u8], values: &[&[u8]], ctx: &mut Context| {
+                    let mut sum = 0;
+                    for value in values {
+                        if let Value::Int(value) = Value::decode(value).unwrap() {
+                            sum += value;
+                        }
+                    }
+                    ctx.write_typed(&Value::decode(key).unwrap(), &Value::Int(sum));
+                });
+
+            reducer.on_start(&mut ctx);
+            crate::io::run_entries(&input[..], &mut reducer, &mut ctx).unwrap();
+            reducer.on_end(&mut ctx);
+        }
+
+        let mut expected = Vec::new();
+        for (key, value) in &[("a\tb", 3), ("c\n", 3)] {
+            expected.extend(Value::from(*key).encode());
+            expected.extend(Value::Int(*value).encode());
+        }
+
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_reducer_raw_bytes() {
+        let env = vec![
+            ("stream.reduce.input", "rawbytes"),
+            ("stream.reduce.output", "rawbytes"),
+        ];
+        let conf = crate::context::Configuration::with_env(env.into_iter());
+
+        let input = b"\0\0\0\x03a\tb\0\0\0\x01\n\0\0\0\x03a\tb\0\0\0\x01\t";
+        let mut output = Vec::new();
+
+        {
+            let mut ctx = Context::with_config(conf, &mut output);
+            let mut reducer =
+                ReducerLifecycle::new(|key: &[u8], values: &[&[u8]], ctx: &mut Context| {
+                    ctx.write(key, &values.concat());
+                });
+
+            reducer.on_start(&mut ctx);
+            crate::io::run_entries(&input[..], &mut reducer, &mut ctx).unwrap();
+            reducer.on_end(&mut ctx);
+        }
+
+        assert_eq!(output, b"\0\0\0\x03a\tb\0\0\0\x02\n\t");
+    }
+
     struct TestPair(Vec<u8>, Vec<Vec<u8>>);
+    struct TestStreamReducer;
+
+    impl StreamReducer for TestStreamReducer {
+        fn reduce(&mut self, key: &[u8], values: &mut Values, ctx: &mut Context) {
+            let mut joined = Vec::new();
+            while let Some(value) = values.next() {
+                if !joined.is_empty() {
+                    joined.push(b',');
+                }
+                joined.extend_from_slice(value);
+            }
+            ctx.write(key, &joined);
+        }
+    }
     struct TestReducer;
 
     impl Contextual for TestPair {}
@@ -0,0 +1,748 @@
+//! Test drivers for stages of a MapReduce job.
+//!
+//! This module offers the `MapDriver`, `ReduceDriver` and `MapReduceDriver`
+//! types, in the style of Hadoop's MRUnit. Each driver feeds a set of input
+//! records through the same lifecycles used when running on a cluster, and
+//! captures all pairs written to the `Context` so that they can be checked.
+//! Counter and status updates made via the `Context` are captured in a set
+//! of `Reports`, which can also be checked against expected values:
+//!
+//! ```rust
+//! # extern crate efflux;
+//! use efflux::prelude::*;
+//! use efflux::testing::MapDriver;
+//!
+//! // emit every word with a count of 1
+//! let mapper = |_key: usize, value: &[u8], ctx: &mut Context| {
+//!     for word in value.split(|b| *b == b' ') {
+//!         ctx.write(word, b"1");
+//!         ctx.counter("words", "total").incr(1);
+//!     }
+//! };
+//!
+//! // check the output of the mapper
+//! MapDriver::new(mapper)
+//!     .with_input("one two")
+//!     .with_output("one", "1")
+//!     .with_output("two", "1")
+//!     .with_counter("words", "total", 2)
+//!     .run_test();
+//! ```
+//!
+//! Unlike a job run via `run_mapper`, drivers do not read a `Configuration`
+//! from the environment. An empty `Configuration` is used unless another is
+//! provided, which keeps tests independent of the machine they run on.
+use std::io::Cursor;
+
+use crate::context::{Configuration, Context, Delimiters, Reports};
+use crate::io::{Format, Lifecycle};
+use crate::local::LocalJob;
+use crate::mapper::{Mapper, MapperLifecycle};
+use crate::reducer::{Reducer, ReducerLifecycle};
+
+/// Pair type to represent a key/value pair emitted by a stage.
+pub type Pair = (Vec<u8>, Vec<u8>);
+
+/// Output structure to represent everything emitted by a stage.
+#[derive(Debug, Default)]
+pub struct Output {
+    pairs: Vec<Pair>,
+    reports: Reports,
+    failure: Option<String>,
+}
+
+impl Output {
+    /// Retrieves the reason the stage failed, if it failed.
+    pub fn failure(&self) -> Option<&str> {
+        self.failure.as_deref()
+    }
+
+    /// Retrieves all pairs emitted by the stage, in order of emission.
+    pub fn pairs(&self) -> &[Pair] {
+        &self.pairs
+    }
+
+    /// Retrieves all counter and status updates made by the stage.
+    pub fn reports(&self) -> &Reports {
+        &self.reports
+    }
+}
+
+/// Driver structure to test a `Mapper`.
+///
+/// Each input is provided to the `Mapper` as a value, with the byte
+/// offset of the value as the key (as would happen on a cluster).
+pub struct MapDriver<M>
+where
+    M: Mapper,
+{
+    mapper: M,
+    conf: Configuration,
+    inputs: Vec<Vec<u8>>,
+    expected: Output,
+}
+
+impl<M> MapDriver<M>
+where
+    M: Mapper,
+{
+    /// Constructs a new `MapDriver` for a `Mapper`.
+    pub fn new(mapper: M) -> Self {
+        Self {
+            mapper,
+            conf: Configuration::default(),
+            inputs: Vec::new(),
+            expected: Output::default(),
+        }
+    }
+
+    /// Sets the job `Configuration` provided to the `Mapper`.
+    pub fn config(mut self, conf: Configuration) -> Self {
+        self.conf = conf;
+        self
+    }
+
+    /// Adds an input value to provide to the `Mapper`.
+    pub fn with_input<V>(mut self, value: V) -> Self
+    where
+        V: AsRef<[u8]>,
+    {
+        self.inputs.push(value.as_ref().to_vec());
+        self
+    }
+
+    /// Adds a key/value pair expected to be emitted by the `Mapper`.
+    pub fn with_output<K, V>(mut self, key: K, value: V) -> Self
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        self.expected.pairs.push(pair(key, value));
+        self
+    }
+
+    /// Adds the total amount a counter is expected to be updated by.
+    pub fn with_counter(mut self, group: &str, label: &str, amount: i64) -> Self {
+        self.expected
+            .reports
+            .record_counter(group.to_owned(), label.to_owned(), amount);
+        self
+    }
+
+    /// Adds a status the `Mapper` is expected to set, in order.
+    pub fn with_status(mut self, status: &str) -> Self {
+        self.expected.reports.record_status(status.to_owned());
+        self
+    }
+
+    /// Executes the `Mapper`, returning everything emitted.
+    pub fn run(self) -> Output {
+        let mut conf = self.conf;
+        conf.insert("mapreduce.task.ismap", "true");
+
+        let mut output = Vec::new();
+        let mut lifecycle = MapperLifecycle::new(self.mapper);
+
+        let (reports, failure) = {
+            let mut ctx = Context::with_config(conf.clone(), &mut output);
+
+            ctx.insert(Reports::new());
+
+            lifecycle.on_start(&mut ctx);
+
+            for input in &self.inputs {
+                if ctx.failed() {
+                    break;
+                }
+                lifecycle.on_entry(input, &mut ctx);
+            }
+
+            if !ctx.failed() {
+                lifecycle.on_end(&mut ctx);
+            }
+
+            ctx.flush();
+            (
+                ctx.take::<Reports>().unwrap_or_default(),
+                ctx.take_failure().map(|err| err.to_string()),
+            )
+        };
+
+        Output {
+            pairs: parse(&conf, &output),
+            reports,
+            failure,
+        }
+    }
+
+    /// Executes the `Mapper`, checking everything emitted is as expected.
+    ///
+    /// All emitted pairs must be expected, whereas only the counters which
+    /// are expected are checked. Statuses are checked when any are expected.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the stage fails, if the emitted pairs differ from the
+    /// expected outputs, or if any expected counter or status updates are
+    /// missing.
+    pub fn run_test(mut self) {
+        let expected = std::mem::take(&mut self.expected);
+        let output = self.run();
+
+        if let Some(failure) = output.failure() {
+            panic!("stage failed: {}", failure);
+        }
+
+        check(&expected.pairs, &output.pairs);
+        check_reports(&expected.reports, &output.reports);
+    }
+}
+
+/// Driver structure to test a `Reducer`.
+///
+/// Inputs are provided to the `Reducer` in the order they were added,
+/// and so should be added in the sorted order of the keys.
+pub struct ReduceDriver<R>
+where
+    R: Reducer,
+{
+    reducer: R,
+    conf: Configuration,
+    inputs: Vec<Pair>,
+    expected: Output,
+}
+
+impl<R> ReduceDriver<R>
+where
+    R: Reducer,
+{
+    /// Constructs a new `ReduceDriver` for a `Reducer`.
+    pub fn new(reducer: R) -> Self {
+        Self {
+            reducer,
+            conf: Configuration::default(),
+            inputs: Vec::new(),
+            expected: Output::default(),
+        }
+    }
+
+    /// Sets the job `Configuration` provided to the `Reducer`.
+    pub fn config(mut self, conf: Configuration) -> Self {
+        self.conf = conf;
+        self
+    }
+
+    /// Adds a key and a group of values to provide to the `Reducer`.
+    pub fn with_input<K, I, V>(mut self, key: K, values: I) -> Self
+    where
+        K: AsRef<[u8]>,
+        I: IntoIterator<Item = V>,
+        V: AsRef<[u8]>,
+    {
+        for value in values {
+            self.inputs.push(pair(&key, value));
+        }
+        self
+    }
+
+    /// Adds a key/value pair expected to be emitted by the `Reducer`.
+    pub fn with_output<K, V>(mut self, key: K, value: V) -> Self
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        self.expected.pairs.push(pair(key, value));
+        self
+    }
+
+    /// Adds the total amount a counter is expected to be updated by.
+    pub fn with_counter(mut self, group: &str, label: &str, amount: i64) -> Self {
+        self.expected
+            .reports
+            .record_counter(group.to_owned(), label.to_owned(), amount);
+        self
+    }
+
+    /// Adds a status the `Reducer` is expected to set, in order.
+    pub fn with_status(mut self, status: &str) -> Self {
+        self.expected.reports.record_status(status.to_owned());
+        self
+    }
+
+    /// Executes the `Reducer`, returning everything emitted.
+    pub fn run(self) -> Output {
+        let mut conf = self.conf;
+        conf.insert("mapreduce.task.ismap", "false");
+
+        let mut output = Vec::new();
+        let mut lifecycle = ReducerLifecycle::new(self.reducer);
+
+        let (reports, failure) = {
+            let mut ctx = Context::with_config(conf.clone(), &mut output);
+
+            ctx.insert(Reports::new());
+
+            lifecycle.on_start(&mut ctx);
+
+            for (key, value) in &self.inputs {
+                if ctx.failed() {
+                    break;
+                }
+                lifecycle.on_pair(key, value, &mut ctx);
+            }
+
+            if !ctx.failed() {
+                lifecycle.on_end(&mut ctx);
+            }
+
+            ctx.flush();
+            (
+                ctx.take::<Reports>().unwrap_or_default(),
+                ctx.take_failure().map(|err| err.to_string()),
+            )
+        };
+
+        Output {
+            pairs: parse(&conf, &output),
+            reports,
+            failure,
+        }
+    }
+
+    /// Executes the `Reducer`, checking everything emitted is as expected.
+    ///
+    /// All emitted pairs must be expected, whereas only the counters which
+    /// are expected are checked. Statuses are checked when any are expected.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the stage fails, if the emitted pairs differ from the
+    /// expected outputs, or if any expected counter or status updates are
+    /// missing.
+    pub fn run_test(mut self) {
+        let expected = std::mem::take(&mut self.expected);
+        let output = self.run();
+
+        if let Some(failure) = output.failure() {
+            panic!("stage failed: {}", failure);
+        }
+
+        check(&expected.pairs, &output.pairs);
+        check_reports(&expected.reports, &output.reports);
+    }
+}
+
+/// Driver structure to test a `Mapper` and `Reducer` together.
+///
+/// This is driven by a `LocalJob`, and so the output of the `Mapper` is
+/// partitioned, sorted and grouped before it reaches the `Reducer`. As
+/// each stage of the job has its own `Context`, only the pairs emitted by
+/// the `Reducer` are captured, whereas counter and status updates are
+/// captured across all stages (including any combiner).
+pub struct MapReduceDriver<M, R>
+where
+    M: Mapper,
+    R: Reducer,
+{
+    job: LocalJob<M, R>,
+    conf: Configuration,
+    inputs: Vec<u8>,
+    expected: Output,
+}
+
+impl<M, R> MapReduceDriver<M, R>
+where
+    M: Mapper,
+    R: Reducer,
+{
+    /// Constructs a new `MapReduceDriver` for a `Mapper` and `Reducer`.
+    pub fn new(mapper: M, reducer: R) -> Self {
+        Self {
+            job: LocalJob::new(mapper, reducer),
+            conf: Configuration::default(),
+            inputs: Vec::new(),
+            expected: Output::default(),
+        }
+    }
+
+    /// Sets a `Reducer` to use as a combiner for the mapping stage.
+    pub fn combiner<C>(mut self, combiner: C) -> Self
+    where
+        C: Reducer + 'static,
+    {
+        self.job = self.job.combiner(combiner);
+        self
+    }
+
+    /// Sets the job `Configuration` provided to all stages.
+    pub fn config(mut self, conf: Configuration) -> Self {
+        self.conf = conf;
+        self
+    }
+
+    /// Adds an input line to provide to the `Mapper`.
+    pub fn with_input<V>(mut self, value: V) -> Self
+    where
+        V: AsRef<[u8]>,
+    {
+        self.inputs.extend_from_slice(value.as_ref());
+        self.inputs.push(b'\n');
+        self
+    }
+
+    /// Adds a key/value pair expected to be emitted by the `Reducer`.
+    pub fn with_output<K, V>(mut self, key: K, value: V) -> Self
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        self.expected.pairs.push(pair(key, value));
+        self
+    }
+
+    /// Adds the total amount a counter is expected to be updated by.
+    ///
+    /// This is the total across all stages of the job.
+    pub fn with_counter(mut self, group: &str, label: &str, amount: i64) -> Self {
+        self.expected
+            .reports
+            .record_counter(group.to_owned(), label.to_owned(), amount);
+        self
+    }
+
+    /// Adds a status the job is expected to set, in order.
+    pub fn with_status(mut self, status: &str) -> Self {
+        self.expected.reports.record_status(status.to_owned());
+        self
+    }
+
+    /// Executes the job, returning everything emitted.
+    ///
+    /// The pairs of the `Output` are those emitted by the `Reducer`.
+    pub fn run(self) -> Output {
+        let mut conf = self.conf;
+        let mut output = Vec::new();
+        let mut reports = Reports::new();
+
+        let failure = self
+            .job
+            .config(conf.clone())
+            .input_reader(Cursor::new(self.inputs))
+            .run_reporting(&mut output, &mut reports)
+            .err()
+            .map(|err| err.to_string());
+
+        conf.insert("mapreduce.task.ismap", "false");
+
+        Output {
+            pairs: parse(&conf, &output),
+            reports,
+            failure,
+        }
+    }
+
+    /// Executes the job, checking everything emitted is as expected.
+    ///
+    /// All emitted pairs must be expected, whereas only the counters which
+    /// are expected are checked. Statuses are checked when any are expected.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the job fails, if the emitted pairs differ from the
+    /// expected outputs, or if any expected counter or status updates are
+    /// missing.
+    pub fn run_test(mut self) {
+        let expected = std::mem::take(&mut self.expected);
+        let output = self.run();
+
+        if let Some(failure) = output.failure() {
+            panic!("job failed: {}", failure);
+        }
+
+        check(&expected.pairs, &output.pairs);
+        check_reports(&expected.reports, &output.reports);
+    }
+}
+
+/// Creates an owned `Pair` from a key and value.
+fn pair<K, V>(key: K, value: V) -> Pair
+where
+    K: AsRef<[u8]>,
+    V: AsRef<[u8]>,
+{
+    (key.as_ref().to_vec(), value.as_ref().to_vec())
+}
+
+/// Parses the output of a stage back into pairs.
+///
+/// This uses the output `Format` of the stage, so text output is split
+/// into pairs in the same way as Hadoop would on a cluster.
+fn parse(conf: &Configuration, mut output: &[u8]) -> Vec<Pair> {
+    let format = Format::output(conf);
+    let mut pairs = Vec::new();
+
+    // text output is split using the output delimiters
+    if format == Format::Text {
+        let delim = Delimiters::new(conf);
+
+        for line in output.split(|b| *b == b'\n') {
+            let (key, value) = delim.split_output(line);
+            pairs.push(pair(key, value));
+        }
+
+        // output always ends with a newline
+        pairs.pop();
+
+        return pairs;
+    }
+
+    // binary output is a stream of keys and values
+    loop {
+        let mut key = Vec::new();
+        let mut value = Vec::new();
+
+        if !format.read_value(&mut output, &mut key).unwrap() {
+            return pairs;
+        }
+
+        format.read_value(&mut output, &mut value).unwrap();
+        pairs.push((key, value));
+    }
+}
+
+/// Checks a set of emitted pairs against a set of expected pairs.
+fn check(expected: &[Pair], actual: &[Pair]) {
+    if expected == actual {
+        return;
+    }
+
+    // format pairs as text to make failures readable
+    let format = |pairs: &[Pair]| {
+        pairs
+            .iter()
+            .map(|(key, value)| {
+                format!(
+                    "({:?}, {:?})",
+                    String::from_utf8_lossy(key),
+                    String::from_utf8_lossy(value)
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    panic!(
+        "unexpected output\n  expected: {:?}\n    actual: {:?}",
+        format(expected),
+        format(actual)
+    );
+}
+
+/// Checks a set of recorded reports contains a set of expected reports.
+fn check_reports(expected: &Reports, actual: &Reports) {
+    for (group, label, amount) in expected.counters() {
+        let total = actual.counter(group, label);
+
+        if total != amount {
+            panic!(
+                "unexpected counter {}/{}\n  expected: {}\n    actual: {}",
+                group, label, amount, total
+            );
+        }
+    }
+
+    if !expected.statuses().is_empty() && expected.statuses() != actual.statuses() {
+        panic!(
+            "unexpected statuses\n  expected: {:?}\n    actual: {:?}",
+            expected.statuses(),
+            actual.statuses()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_driver() {
+        let output = MapDriver::new(|key: usize, value: &[u8], ctx: &mut Context| {
+            ctx.write_fmt(String::from_utf8_lossy(value), key);
+        })
+        .with_input("one")
+        .with_input("two")
+        .run();
+
+        assert_eq!(output.pairs(), &[pair("one", "0"), pair("two", "4")]);
+    }
+
+    #[test]
+    fn test_map_driver_config() {
+        let mut conf = Configuration::default();
+        conf.insert("stream.map.output", "rawbytes");
+
+        MapDriver::new(|_key: usize, value: &[u8], ctx: &mut Context| {
+            ctx.write(value, b"\n");
+        })
+        .config(conf)
+        .with_input("a\tb")
+        .with_output("a\tb", "\n")
+        .run_test();
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected output")]
+    fn test_map_driver_failure() {
+        MapDriver::new(|_key: usize, value: &[u8], ctx: &mut Context| {
+            ctx.write(value, b"1");
+        })
+        .with_input("one")
+        .with_output("one", "2")
+        .run_test();
+    }
+
+    #[test]
+    fn test_reduce_driver() {
+        ReduceDriver::new(TestReducer)
+            .with_input("one", ["1", "2"])
+            .with_input("two", ["3"])
+            .with_output("one", "3")
+            .with_output("two", "3")
+            .run_test();
+    }
+
+    #[test]
+    fn test_reduce_driver_key_fields() {
+        let mut conf = Configuration::default();
+        conf.insert("stream.num.reduce.output.key.fields", "2");
+
+        let output = ReduceDriver::new(|key: &[u8], values: &[&[u8]], ctx: &mut Context| {
+            ctx.write(key, &values.join(&b'\t')[..]);
+        })
+        .config(conf)
+        .with_input("a", ["b", "c"])
+        .run();
+
+        assert_eq!(output.pairs(), &[pair("a\tb", "c")]);
+    }
+
+    #[test]
+    fn test_driver_reports() {
+        let reducer = |key: &[u8], values: &[&[u8]], ctx: &mut Context| {
+            ctx.counter("keys", "total").incr(1);
+            ctx.counter("values", "total").incr(values.len() as i64);
+            ctx.update_status(String::from_utf8_lossy(key));
+        };
+
+        ReduceDriver::new(reducer)
+            .with_input("one", ["1", "2"])
+            .with_input("two", ["3"])
+            .with_counter("values", "total", 3)
+            .with_status("one")
+            .with_status("two")
+            .run_test();
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected counter keys/total")]
+    fn test_driver_reports_failure() {
+        MapDriver::new(|_key: usize, _value: &[u8], ctx: &mut Context| {
+            ctx.counter("keys", "total").incr(1);
+        })
+        .with_input("one")
+        .with_counter("keys", "total", 2)
+        .run_test();
+    }
+
+    #[test]
+    fn test_map_reduce_driver() {
+        let mapper = |_key: usize, value: &[u8], ctx: &mut Context| {
+            for word in value.split(|b| *b == b' ') {
+                ctx.write(word, b"1");
+            }
+        };
+
+        MapReduceDriver::new(mapper, TestReducer)
+            .combiner(TestReducer)
+            .with_input("two one two")
+            .with_input("one")
+            .with_output("one", "2")
+            .with_output("two", "2")
+            .run_test();
+    }
+
+    #[test]
+    fn test_map_reduce_driver_reports() {
+        let mapper = |_key: usize, value: &[u8], ctx: &mut Context| {
+            ctx.update_status("mapping");
+            for word in value.split(|b| *b == b' ') {
+                ctx.counter("words", "mapped").incr(1);
+                ctx.write(word, b"1");
+            }
+        };
+
+        let reducer = |key: &[u8], values: &[&[u8]], ctx: &mut Context| {
+            ctx.counter("words", "reduced").incr(values.len() as i64);
+            ctx.update_status(String::from_utf8_lossy(key));
+            ctx.write(key, b"");
+        };
+
+        let combiner = |key: &[u8], values: &[&[u8]], ctx: &mut Context| {
+            ctx.counter("words", "combined").incr(values.len() as i64);
+            ctx.write(key, values[0]);
+        };
+
+        let driver = || {
+            MapReduceDriver::new(mapper, reducer)
+                .combiner(combiner)
+                .with_input("two one two")
+                .with_input("one")
+                .with_output("one", "")
+                .with_output("two", "")
+        };
+
+        driver()
+            .with_counter("words", "mapped", 4)
+            .with_counter("words", "combined", 4)
+            .with_counter("words", "reduced", 2)
+            .with_status("mapping")
+            .with_status("mapping")
+            .with_status("one")
+            .with_status("two")
+            .run_test();
+
+        let output = driver().run();
+
+        assert!(output.failure().is_none());
+        assert_eq!(output.reports().counter("words", "mapped"), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected counter words/mapped")]
+    fn test_map_reduce_driver_reports_failure() {
+        let mapper = |_key: usize, value: &[u8], ctx: &mut Context| {
+            ctx.counter("words", "mapped").incr(1);
+            ctx.write(value, b"1");
+        };
+
+        MapReduceDriver::new(mapper, TestReducer)
+            .with_input("one")
+            .with_output("one", "1")
+            .with_counter("words", "mapped", 2)
+            .run_test();
+    }
+
+    struct TestReducer;
+
+    impl Reducer for TestReducer {
+        fn reduce(&mut self, key: &[u8], values: &[&[u8]], ctx: &mut Context) {
+            let mut count = 0;
+            for value in values {
+                count += std::str::from_utf8(value)
+                    .unwrap()
+                    .parse::<usize>()
+                    .unwrap();
+            }
+            ctx.write_fmt(std::str::from_utf8(key).unwrap(), count);
+        }
+    }
+}
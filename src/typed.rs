@@ -0,0 +1,833 @@
+//! Typed stages based on `serde`, enabled via the `serde` feature.
+//!
+//! This module offers the `TypedMapper` and `TypedReducer` traits, which
+//! receive decoded values rather than raw bytes, and emit values which
+//! are encoded automatically. Encoding is controlled by the `Codec` set
+//! in `efflux.typed.codec` (either `text` or `json`), which defaults to
+//! plain text. Plain text is decoded based on the type being requested,
+//! and strings containing a tab, line break or the output separator are
+//! rejected when encoding, as they would corrupt the streaming framing.
+//!
+//! Input keys are decoded alongside values. When the input of a mapping
+//! stage contains keys (i.e. `stream.map.input.ignoreKey` is `false`),
+//! each line is split into a pair; otherwise the key is the byte offset
+//! of the line, and so must be decoded into an integer type.
+//!
+//! Typed stages only support text input and output, as values are framed
+//! using the streaming separators; a stage configured with typedbytes or
+//! rawbytes (i.e. `stream.map.output=typedbytes`) fails during setup.
+//!
+//! Records which fail to decode (and pairs which fail to encode) are
+//! skipped and counted under the `efflux` counter group, rather than
+//! causing the task to panic. Typed stages are adapted into a `Mapper`
+//! or `Reducer`, and so can be used anywhere the base traits are:
+//!
+//! ```rust,no_run
+//! # extern crate efflux;
+//! use efflux::prelude::*;
+//! use efflux::typed::{self, Emitter, TypedMapper};
+//!
+//! struct WordcountMapper;
+//!
+//! // emit every word with a count of 1
+//! impl TypedMapper<String, u64> for WordcountMapper {
+//!     type InputKey = usize;
+//!     type InputValue = String;
+//!
+//!     fn map(&mut self, _key: usize, value: String, out: &mut Emitter<String, u64>) {
+//!         for word in value.split(' ') {
+//!             out.emit(&word.to_string(), &1);
+//!         }
+//!     }
+//! }
+//!
+//! efflux::run_mapper(typed::mapper(WordcountMapper));
+//! ```
+use serde::de::value::{StrDeserializer, U64Deserializer};
+use serde::de::{DeserializeOwned, Error as _, Unexpected, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+use std::fmt::Display;
+use std::marker::PhantomData;
+use std::str::{self, FromStr};
+
+use crate::context::{Configuration, Context, Delimiters};
+use crate::error::Error;
+use crate::io::{self, Format};
+use crate::mapper::Mapper;
+use crate::reducer::Reducer;
+
+/// Trait to represent a typed mapping stage of MapReduce.
+///
+/// Input keys and values are decoded into the `InputKey` and `InputValue`
+/// types before being passed to the `map` handler, which emits pairs of
+/// type `K` and `V`.
+pub trait TypedMapper<K, V> {
+    /// Type to decode input keys into.
+    type InputKey: DeserializeOwned;
+
+    /// Type to decode input values into.
+    type InputValue: DeserializeOwned;
+
+    /// Setup handler for the current `TypedMapper`.
+    fn setup(&mut self, _ctx: &mut Context) {}
+
+    /// Mapping handler for the current `TypedMapper`.
+    fn map(&mut self, key: Self::InputKey, value: Self::InputValue, out: &mut Emitter<K, V>);
+
+    /// Cleanup handler for the current `TypedMapper`.
+    fn cleanup(&mut self, _ctx: &mut Context) {}
+}
+
+/// Trait to represent a typed reduction stage of MapReduce.
+///
+/// Input keys and values are decoded into `K` and `V` before being
+/// passed to the `reduce` handler, which emits pairs of the types set
+/// by `Key` and `Value`.
+pub trait TypedReducer<K, V> {
+    /// Type of keys emitted by the `TypedReducer`.
+    type Key: Serialize;
+
+    /// Type of values emitted by the `TypedReducer`.
+    type Value: Serialize;
+
+    /// Setup handler for the current `TypedReducer`.
+    fn setup(&mut self, _ctx: &mut Context) {}
+
+    /// Reduction handler for the current `TypedReducer`.
+    fn reduce(&mut self, key: K, values: Vec<V>, out: &mut Emitter<Self::Key, Self::Value>);
+
+    /// Cleanup handler for the current `TypedReducer`.
+    fn cleanup(&mut self, _ctx: &mut Context) {}
+}
+
+/// Codec enum to represent the encoding of typed keys and values.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Codec {
+    /// Plain text, supporting only strings, numbers and booleans.
+    #[default]
+    Text,
+    /// JSON, supporting any type.
+    Json,
+}
+
+impl Codec {
+    /// Creates a new `Codec` from a job `Configuration`.
+    ///
+    /// Returns an `Error` if `efflux.typed.codec` is set to an unknown codec.
+    pub fn new(conf: &Configuration) -> Result<Self, Error> {
+        match conf.get("efflux.typed.codec").map(str::trim) {
+            None | Some("text") => Ok(Codec::Text),
+            Some("json") => Ok(Codec::Json),
+            Some(codec) => Err(Error::Config(format!(
+                "unknown codec for efflux.typed.codec: {}",
+                codec
+            ))),
+        }
+    }
+
+    /// Decodes a value of type `T` from a slice of bytes.
+    ///
+    /// Plain text is parsed based on the type of `T`, so `42` decodes as a
+    /// string when `T` is a `String`, and a number when `T` is numeric. If
+    /// `T` accepts any type (such as `serde_json::Value`), numbers and
+    /// booleans are detected before falling back to a string.
+    pub fn decode<T>(self, bytes: &[u8]) -> serde_json::Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        if self == Codec::Json {
+            return serde_json::from_slice(bytes);
+        }
+
+        let text = str::from_utf8(bytes).map_err(serde_json::Error::custom)?;
+
+        T::deserialize(TextDeserializer { text })
+    }
+
+    /// Encodes a value of type `T` into a vector of bytes.
+    pub fn encode<T>(self, value: &T) -> serde_json::Result<Vec<u8>>
+    where
+        T: Serialize,
+    {
+        if self == Codec::Json {
+            return serde_json::to_vec(value);
+        }
+
+        match serde_json::to_value(value)? {
+            Value::Null => Ok(Vec::new()),
+            Value::String(string) if string.contains(['\t', '\n', '\r']) => Err(
+                serde_json::Error::custom("text values cannot contain tabs or line breaks"),
+            ),
+            Value::String(string) => Ok(string.into_bytes()),
+            value @ Value::Bool(_) | value @ Value::Number(_) => Ok(value.to_string().into_bytes()),
+            _ => Err(serde_json::Error::custom(
+                "compound values require the json codec",
+            )),
+        }
+    }
+}
+
+/// Emitter structure to write typed pairs to a `Context`.
+pub struct Emitter<'e, 'c, K, V> {
+    ctx: &'e mut Context<'c>,
+    codec: Codec,
+    marker: PhantomData<fn(&K, &V)>,
+}
+
+impl<'e, 'c, K, V> Emitter<'e, 'c, K, V>
+where
+    K: Serialize,
+    V: Serialize,
+{
+    /// Creates a new `Emitter` against a `Context`.
+    fn new(ctx: &'e mut Context<'c>, codec: Codec) -> Self {
+        Self {
+            ctx,
+            codec,
+            marker: PhantomData,
+        }
+    }
+
+    /// Retrieves a mutable reference to the underlying `Context`.
+    pub fn context(&mut self) -> &mut Context<'c> {
+        self.ctx
+    }
+
+    /// Encodes and writes a key/value pair to the stage output.
+    ///
+    /// Pairs which fail to encode are skipped and counted. This includes
+    /// keys containing the output separator, along with plain text values
+    /// containing it, as the pair could not be split back apart.
+    pub fn emit(&mut self, key: &K, value: &V) {
+        match (self.codec.encode(key), self.codec.encode(value)) {
+            (Ok(key), Ok(value)) if self.framed(&key, &value) => self.ctx.write(&key, &value),
+            _ => {
+                self.ctx.counter("efflux", "TYPED_ENCODE_ERRORS").incr(1);
+            }
+        }
+    }
+
+    /// Determines whether an encoded pair can be framed by the separator.
+    fn framed(&self, key: &[u8], value: &[u8]) -> bool {
+        let separator = match self.ctx.get::<Delimiters>() {
+            Some(delim) if !delim.output().is_empty() => delim.output(),
+            _ => return true,
+        };
+
+        let contains = |bytes: &[u8]| twoway::find_bytes(bytes, separator).is_some();
+
+        !contains(key) && (self.codec == Codec::Json || !contains(value))
+    }
+}
+
+/// Deserializer structure to decode plain text based on the requested type.
+///
+/// Text has no structure of its own, so each value is parsed using the
+/// type hint provided by the type being decoded. Types without a hint
+/// have any numbers and booleans detected, falling back to a string.
+struct TextDeserializer<'de> {
+    text: &'de str,
+}
+
+impl TextDeserializer<'_> {
+    /// Parses the text into a type `T`.
+    fn parse<T>(&self) -> serde_json::Result<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.text.parse().map_err(serde_json::Error::custom)
+    }
+}
+
+/// Implements deserialization of types parsed directly from the text.
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident),*) => {
+        $(
+            fn $method<T>(self, visitor: T) -> serde_json::Result<T::Value>
+            where
+                T: Visitor<'de>,
+            {
+                visitor.$visit(self.parse()?)
+            }
+        )*
+    };
+}
+
+/// Implements deserialization of types which are unsupported by plain text.
+macro_rules! deserialize_compound {
+    ($($method:ident($($arg:ident: $ty:ty),*)),*) => {
+        $(
+            fn $method<T>(self, $(_: $ty,)* _visitor: T) -> serde_json::Result<T::Value>
+            where
+                T: Visitor<'de>,
+            {
+                Err(serde_json::Error::custom(
+                    "compound values require the json codec",
+                ))
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for TextDeserializer<'de> {
+    type Error = serde_json::Error;
+
+    fn deserialize_any<T>(self, visitor: T) -> serde_json::Result<T::Value>
+    where
+        T: Visitor<'de>,
+    {
+        match serde_json::from_str(self.text) {
+            Ok(Value::Bool(bool)) => visitor.visit_bool(bool),
+            Ok(Value::Number(number)) => number.deserialize_any(visitor),
+            _ => visitor.visit_borrowed_str(self.text),
+        }
+    }
+
+    deserialize_parsed!(
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char
+    );
+
+    deserialize_compound!(
+        deserialize_seq(),
+        deserialize_tuple(_len: usize),
+        deserialize_tuple_struct(_name: &'static str, _len: usize),
+        deserialize_map(),
+        deserialize_struct(_name: &'static str, _fields: &'static [&'static str])
+    );
+
+    fn deserialize_str<T>(self, visitor: T) -> serde_json::Result<T::Value>
+    where
+        T: Visitor<'de>,
+    {
+        visitor.visit_borrowed_str(self.text)
+    }
+
+    fn deserialize_string<T>(self, visitor: T) -> serde_json::Result<T::Value>
+    where
+        T: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_identifier<T>(self, visitor: T) -> serde_json::Result<T::Value>
+    where
+        T: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<T>(self, visitor: T) -> serde_json::Result<T::Value>
+    where
+        T: Visitor<'de>,
+    {
+        visitor.visit_borrowed_bytes(self.text.as_bytes())
+    }
+
+    fn deserialize_byte_buf<T>(self, visitor: T) -> serde_json::Result<T::Value>
+    where
+        T: Visitor<'de>,
+    {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<T>(self, visitor: T) -> serde_json::Result<T::Value>
+    where
+        T: Visitor<'de>,
+    {
+        // empty text is written for `None`, so reads back as `None`
+        if self.text.is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_unit<T>(self, visitor: T) -> serde_json::Result<T::Value>
+    where
+        T: Visitor<'de>,
+    {
+        if self.text.is_empty() {
+            visitor.visit_unit()
+        } else {
+            Err(serde_json::Error::invalid_type(
+                Unexpected::Str(self.text),
+                &visitor,
+            ))
+        }
+    }
+
+    fn deserialize_unit_struct<T>(
+        self,
+        _name: &'static str,
+        visitor: T,
+    ) -> serde_json::Result<T::Value>
+    where
+        T: Visitor<'de>,
+    {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<T>(
+        self,
+        _name: &'static str,
+        visitor: T,
+    ) -> serde_json::Result<T::Value>
+    where
+        T: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<T>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: T,
+    ) -> serde_json::Result<T::Value>
+    where
+        T: Visitor<'de>,
+    {
+        // only unit variants can be named in plain text
+        visitor.visit_enum(StrDeserializer::new(self.text))
+    }
+
+    fn deserialize_ignored_any<T>(self, visitor: T) -> serde_json::Result<T::Value>
+    where
+        T: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+}
+
+/// Creates the `Codec` of a typed stage from a job `Configuration`.
+///
+/// Returns an `Error` if either side of the stage uses a binary `Format`.
+fn stage_codec(conf: &Configuration) -> Result<Codec, Error> {
+    for (side, format) in [
+        ("input", Format::input(conf)),
+        ("output", Format::output(conf)),
+    ] {
+        if format != Format::Text {
+            return Err(Error::Config(format!(
+                "typed stages require text {}, found {:?}",
+                side, format
+            )));
+        }
+    }
+
+    Codec::new(conf)
+}
+
+/// Adapts a `TypedMapper` into a `Mapper`.
+pub fn mapper<M, K, V>(mapper: M) -> impl Mapper
+where
+    M: TypedMapper<K, V>,
+    K: Serialize,
+    V: Serialize,
+{
+    TypedMapperStage {
+        mapper,
+        codec: Codec::Text,
+        keyed: false,
+        marker: PhantomData,
+    }
+}
+
+/// Adapts a `TypedReducer` into a `Reducer`.
+pub fn reducer<R, K, V>(reducer: R) -> impl Reducer
+where
+    R: TypedReducer<K, V>,
+    K: DeserializeOwned,
+    V: DeserializeOwned,
+{
+    TypedReducerStage {
+        reducer,
+        codec: Codec::Text,
+        marker: PhantomData,
+    }
+}
+
+/// Stage structure to represent a `TypedMapper` as a `Mapper`.
+struct TypedMapperStage<M, K, V> {
+    mapper: M,
+    codec: Codec,
+    keyed: bool,
+    marker: PhantomData<fn(&K, &V)>,
+}
+
+impl<M, K, V> Mapper for TypedMapperStage<M, K, V>
+where
+    M: TypedMapper<K, V>,
+    K: Serialize,
+    V: Serialize,
+{
+    fn setup(&mut self, ctx: &mut Context) {
+        // text input provides keys as part of each line, when keyed
+        let (codec, keyed) = match ctx.get::<Configuration>() {
+            Some(conf) => (stage_codec(conf), io::map_keyed(conf)),
+            None => (Ok(Codec::Text), false),
+        };
+
+        self.codec = match codec {
+            Ok(codec) => codec,
+            Err(err) => return ctx.fail_with(err),
+        };
+
+        self.keyed = keyed;
+        self.mapper.setup(ctx);
+    }
+
+    fn map(&mut self, offset: usize, entry: &[u8], ctx: &mut Context) {
+        let pair = if self.keyed {
+            let (key, value) = ctx.get::<Delimiters>().unwrap().split_input(entry);
+            self.codec
+                .decode(key)
+                .and_then(|key| Ok((key, self.codec.decode(value)?)))
+        } else {
+            M::InputKey::deserialize(U64Deserializer::new(offset as u64))
+                .and_then(|key| Ok((key, self.codec.decode(entry)?)))
+        };
+
+        match pair {
+            Ok((key, value)) => self
+                .mapper
+                .map(key, value, &mut Emitter::new(ctx, self.codec)),
+            Err(_) => {
+                ctx.counter("efflux", "TYPED_DECODE_ERRORS").incr(1);
+            }
+        }
+    }
+
+    fn cleanup(&mut self, ctx: &mut Context) {
+        self.mapper.cleanup(ctx);
+    }
+}
+
+/// Stage structure to represent a `TypedReducer` as a `Reducer`.
+struct TypedReducerStage<R, K, V> {
+    reducer: R,
+    codec: Codec,
+    marker: PhantomData<fn() -> (K, V)>,
+}
+
+impl<R, K, V> Reducer for TypedReducerStage<R, K, V>
+where
+    R: TypedReducer<K, V>,
+    K: DeserializeOwned,
+    V: DeserializeOwned,
+{
+    fn setup(&mut self, ctx: &mut Context) {
+        let codec = ctx
+            .get::<Configuration>()
+            .map_or(Ok(Codec::Text), stage_codec);
+
+        self.codec = match codec {
+            Ok(codec) => codec,
+            Err(err) => return ctx.fail_with(err),
+        };
+
+        self.reducer.setup(ctx);
+    }
+
+    fn reduce(&mut self, key: &[u8], values: &[&[u8]], ctx: &mut Context) {
+        // a key which can't be decoded skips the entire group
+        let key = match self.codec.decode(key) {
+            Ok(key) => key,
+            Err(_) => {
+                ctx.counter("efflux", "TYPED_DECODE_ERRORS")
+                    .incr(values.len() as i64);
+                return;
+            }
+        };
+
+        // decode all values, skipping any which are invalid
+        let mut decoded = Vec::with_capacity(values.len());
+        for value in values {
+            match self.codec.decode(value) {
+                Ok(value) => decoded.push(value),
+                Err(_) => {
+                    ctx.counter("efflux", "TYPED_DECODE_ERRORS").incr(1);
+                }
+            }
+        }
+
+        self.reducer
+            .reduce(key, decoded, &mut Emitter::new(ctx, self.codec));
+    }
+
+    fn cleanup(&mut self, ctx: &mut Context) {
+        self.reducer.cleanup(ctx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_text_codec() {
+        let codec = Codec::Text;
+
+        assert_eq!(codec.decode::<u64>(b"42").unwrap(), 42);
+        assert_eq!(codec.decode::<f64>(b"-1.5").unwrap(), -1.5);
+        assert!(codec.decode::<bool>(b"true").unwrap());
+        assert_eq!(codec.decode::<String>(b"42").unwrap(), "42");
+        assert_eq!(codec.decode::<String>(b"").unwrap(), "");
+        assert_eq!(codec.decode::<Option<u8>>(b"").unwrap(), None);
+        assert_eq!(codec.decode::<Option<u8>>(b"7").unwrap(), Some(7));
+
+        assert!(codec.decode::<u64>(b"four").is_err());
+        assert!(codec.decode::<u8>(b"256").is_err());
+        assert!(codec.decode::<Vec<u8>>(b"1").is_err());
+
+        assert_eq!(codec.encode(&42).unwrap(), b"42");
+        assert_eq!(codec.encode(&"a b").unwrap(), b"a b");
+        assert_eq!(codec.encode(&Some(false)).unwrap(), b"false");
+        assert_eq!(codec.encode(&None::<u8>).unwrap(), b"");
+
+        assert!(codec.encode(&vec![1, 2]).is_err());
+        assert!(codec.encode(&"a\tb").is_err());
+        assert!(codec.encode(&"a\nb").is_err());
+        assert!(codec.encode(&"a\rb").is_err());
+    }
+
+    #[test]
+    fn test_text_codec_hints() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        #[serde(untagged)]
+        enum Field {
+            Number(u64),
+            Text(String),
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        #[serde(rename_all = "lowercase")]
+        enum Level {
+            Info,
+            Warn,
+        }
+
+        let codec = Codec::Text;
+
+        assert_eq!(codec.decode::<Value>(b"42").unwrap(), Value::from(42));
+        assert_eq!(codec.decode::<Value>(b"-1.5").unwrap(), Value::from(-1.5));
+        assert_eq!(codec.decode::<Value>(b"true").unwrap(), Value::from(true));
+        assert_eq!(codec.decode::<Value>(b"four").unwrap(), Value::from("four"));
+
+        assert_eq!(codec.decode::<Field>(b"42").unwrap(), Field::Number(42));
+        assert_eq!(
+            codec.decode::<Field>(b"42a").unwrap(),
+            Field::Text("42a".to_string())
+        );
+
+        assert_eq!(codec.decode::<Level>(b"warn").unwrap(), Level::Warn);
+        assert!(codec.decode::<Level>(b"error").is_err());
+        assert_ne!(codec.decode::<Level>(b"info").unwrap(), Level::Warn);
+    }
+
+    #[test]
+    fn test_json_codec() {
+        let codec = Codec::Json;
+
+        let mut map = BTreeMap::new();
+        map.insert("key".to_string(), vec![1, 2]);
+
+        assert_eq!(codec.encode(&map).unwrap(), br#"{"key":[1,2]}"#);
+        assert_eq!(codec.encode(&"text").unwrap(), br#""text""#);
+
+        assert_eq!(
+            codec
+                .decode::<BTreeMap<String, Vec<u8>>>(br#"{"key":[1,2]}"#)
+                .unwrap(),
+            map
+        );
+
+        assert!(codec.decode::<String>(b"text").is_err());
+    }
+
+    #[test]
+    fn test_codec_config() {
+        let env = vec![("efflux.typed.codec", "json")];
+        let conf = Configuration::with_env(env.into_iter());
+
+        assert_eq!(Codec::new(&conf).unwrap(), Codec::Json);
+        assert_eq!(Codec::new(&Configuration::default()).unwrap(), Codec::Text);
+
+        let env = vec![("efflux.typed.codec", "JSON")];
+        let conf = Configuration::with_env(env.into_iter());
+
+        assert_eq!(
+            Codec::new(&conf).unwrap_err().to_string(),
+            "invalid configuration: unknown codec for efflux.typed.codec: JSON"
+        );
+    }
+
+    #[test]
+    fn test_typed_unknown_codec() {
+        let env = vec![("efflux.typed.codec", "yaml")];
+        let conf = Configuration::with_env(env.into_iter());
+
+        let mut output = Vec::new();
+        let mut ctx = Context::with_config(conf, &mut output);
+        let mut stage = mapper(TestMapper);
+
+        stage.setup(&mut ctx);
+
+        assert!(matches!(ctx.take_failure(), Some(Error::Config(_))));
+    }
+
+    #[test]
+    fn test_typed_binary_formats() {
+        for (key, value) in [
+            ("stream.map.input", "typedbytes"),
+            ("stream.map.output", "rawbytes"),
+        ] {
+            let env = vec![("mapreduce.task.ismap", "true"), (key, value)];
+            let conf = Configuration::with_env(env.into_iter());
+
+            let mut ctx = Context::with_config(conf, std::io::sink());
+            let mut stage = mapper(TestMapper);
+
+            stage.setup(&mut ctx);
+
+            assert!(matches!(ctx.take_failure(), Some(Error::Config(_))));
+        }
+
+        let env = vec![("stream.reduce.output", "typedbytes")];
+        let conf = Configuration::with_env(env.into_iter());
+
+        let mut ctx = Context::with_config(conf, std::io::sink());
+        let mut stage = reducer(TestReducer);
+
+        stage.setup(&mut ctx);
+
+        assert_eq!(
+            ctx.take_failure().unwrap().to_string(),
+            "invalid configuration: typed stages require text output, found TypedBytes"
+        );
+    }
+
+    #[test]
+    fn test_typed_mapper() {
+        let input = b"one 2\nthree four\nfive 6\n";
+        let mut output = Vec::new();
+
+        crate::run_mapper_with(&input[..], &mut output, mapper(TestMapper)).unwrap();
+
+        assert_eq!(output, b"one\t2\nfive\t6\n");
+    }
+
+    #[test]
+    fn test_typed_mapper_keys() {
+        let env = vec![
+            ("mapreduce.task.ismap", "true"),
+            ("stream.map.input.ignoreKey", "false"),
+        ];
+        let conf = Configuration::with_env(env.into_iter());
+
+        let input = b"7\tone\nseven\ttwo\n8\tthree\n";
+        let mut output = Vec::new();
+
+        let mut ctx = Context::with_config(conf, &mut output);
+        let mut stage = crate::mapper::MapperLifecycle::new(mapper(TestKeyedMapper));
+
+        crate::io::Lifecycle::on_start(&mut stage, &mut ctx);
+        crate::io::run_entries(&input[..], &mut stage, &mut ctx).unwrap();
+        crate::io::Lifecycle::on_end(&mut stage, &mut ctx);
+
+        assert_eq!(ctx.counter("efflux", "TYPED_DECODE_ERRORS").value(), 1);
+
+        drop(ctx);
+
+        assert_eq!(output, b"one\t14\nthree\t16\n");
+    }
+
+    #[test]
+    fn test_typed_emit_framing() {
+        let mut output = Vec::new();
+        let mut ctx = Context::with_config(Configuration::default(), &mut output);
+
+        {
+            let mut out = Emitter::<String, String>::new(&mut ctx, Codec::Text);
+
+            out.emit(&"key".to_string(), &"value".to_string());
+            out.emit(&"k\tey".to_string(), &"value".to_string());
+            out.emit(&"key".to_string(), &"line\nbreak".to_string());
+        }
+
+        {
+            let mut out = Emitter::<String, String>::new(&mut ctx, Codec::Json);
+
+            out.emit(&"k\tey".to_string(), &"va\tlue".to_string());
+        }
+
+        assert_eq!(ctx.counter("efflux", "TYPED_ENCODE_ERRORS").value(), 2);
+
+        drop(ctx);
+
+        assert_eq!(output, b"key\tvalue\n\"k\\tey\"\t\"va\\tlue\"\n");
+    }
+
+    #[test]
+    fn test_typed_reducer() {
+        let input = b"one\t1\none\tone\none\t2\ntwo\t3\n";
+        let mut output = Vec::new();
+
+        crate::run_reducer_with(&input[..], &mut output, reducer(TestReducer)).unwrap();
+
+        assert_eq!(output, b"one\t3\ntwo\t3\n");
+    }
+
+    struct TestMapper;
+    struct TestKeyedMapper;
+    struct TestReducer;
+
+    impl TypedMapper<String, u64> for TestKeyedMapper {
+        type InputKey = u64;
+        type InputValue = String;
+
+        fn map(&mut self, key: u64, value: String, out: &mut Emitter<String, u64>) {
+            out.emit(&value, &(key * 2));
+        }
+    }
+
+    impl TypedMapper<String, u64> for TestMapper {
+        type InputKey = usize;
+        type InputValue = String;
+
+        fn map(&mut self, _key: usize, value: String, out: &mut Emitter<String, u64>) {
+            let mut split = value.splitn(2, ' ');
+            let word = split.next().unwrap().to_string();
+
+            if let Ok(count) = split.next().unwrap().parse() {
+                out.emit(&word, &count);
+            }
+        }
+    }
+
+    impl TypedReducer<String, u64> for TestReducer {
+        type Key = String;
+        type Value = u64;
+
+        fn reduce(&mut self, key: String, values: Vec<u64>, out: &mut Emitter<String, u64>) {
+            out.emit(&key, &values.iter().sum());
+        }
+    }
+}